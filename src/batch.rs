@@ -0,0 +1,157 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::Duration;
+
+use crossbeam::channel::{RecvTimeoutError, Sender};
+use parking_lot::Mutex;
+
+use crate::{Error, Group, Stats};
+
+type BatchFn<K, T, E> = dyn Fn(&[K]) -> Vec<Result<T, E>> + Send + Sync;
+
+/// BatchGroup coalesces calls for different keys into batches, like a
+/// DataLoader.
+///
+/// Keys submitted within a window, or until a batch is full, are loaded by
+/// a single call of the batch func, and every caller gets the res of its
+/// key. Calls for a key that is already being loaded are suppressed like
+/// in a [`Group`].
+pub struct BatchGroup<K, T, E = anyhow::Error>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    group: Group<K, T, E>,
+    batch_fn: Box<BatchFn<K, T, E>>,
+    window: Duration,
+    max_batch: usize,
+    queue: Mutex<Queue<K, T, E>>,
+}
+
+// Queue is the batch being collected
+struct Queue<K, T, E> {
+    // bumped whenever the batch is taken
+    generation: u64,
+    keys: Vec<K>,
+    sends: Vec<Sender<Result<T, E>>>,
+}
+
+impl<K, T, E> Queue<K, T, E> {
+    fn new(generation: u64) -> Queue<K, T, E> {
+        Queue {
+            generation,
+            keys: Vec::new(),
+            sends: Vec::new(),
+        }
+    }
+
+    // take takes the batch, leaving an empty one of the next generation
+    fn take(&mut self) -> Queue<K, T, E> {
+        let next = Queue::new(self.generation + 1);
+        std::mem::replace(self, next)
+    }
+}
+
+impl<K, T, E> BatchGroup<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    // new creates a batch group that loads batches of keys with batch_fn,
+    // which returns one res per key, in the order they are given.
+    pub fn new<F>(batch_fn: F) -> BatchGroup<K, T, E>
+    where
+        F: Fn(&[K]) -> Vec<Result<T, E>> + Send + Sync + 'static,
+    {
+        BatchGroup {
+            group: Group::default(),
+            batch_fn: Box::new(batch_fn),
+            window: Duration::from_millis(1),
+            max_batch: usize::MAX,
+            queue: Mutex::new(Queue::new(0)),
+        }
+    }
+
+    // window sets how long a batch collects keys after its first one.
+    // Defaults to a millisecond.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    // max_batch sets the number of keys after which a batch is loaded
+    // without waiting for its window to close. Batches are unbounded by
+    // default.
+    pub fn max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    // stats returns a snapshot of the counters of the calls for single keys.
+    pub fn stats(&self) -> Stats {
+        self.group.stats()
+    }
+
+    // load loads key as part of a batch and returns its res. The bool value
+    // indicates whether it was given to multiple callers of the key.
+    pub fn load<Q>(&self, key: &Q) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.group.go(key, || self.submit(key.to_owned()))
+    }
+
+    // submit adds key to the batch being collected and waits for its res.
+    // The caller that opens a batch loads it once its window closes, unless
+    // the caller that fills it up did so already.
+    fn submit(&self, key: K) -> Result<T, E> {
+        let (send, recv) = crossbeam::channel::bounded(1);
+        let mut queue = self.queue.lock();
+        let generation = queue.generation;
+        let opens = queue.keys.is_empty();
+        queue.keys.push(key);
+        queue.sends.push(send);
+
+        if queue.keys.len() >= self.max_batch {
+            let batch = queue.take();
+            drop(queue);
+            self.run(batch);
+        } else if opens {
+            drop(queue);
+            match recv.recv_timeout(self.window) {
+                Ok(res) => return res,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => panic!("singleflight: batch func panicked"),
+            }
+            let mut queue = self.queue.lock();
+            if queue.generation == generation {
+                let batch = queue.take();
+                drop(queue);
+                self.run(batch);
+            }
+        } else {
+            drop(queue);
+        }
+
+        match recv.recv() {
+            Ok(res) => res,
+            Err(_) => panic!("singleflight: batch func panicked"),
+        }
+    }
+
+    // run loads a batch and hands every caller in it the res of its key. A
+    // panic of batch_fn disconnects the other callers, which panic in turn.
+    fn run(&self, batch: Queue<K, T, E>) {
+        let results = (self.batch_fn)(&batch.keys);
+        assert_eq!(
+            results.len(),
+            batch.keys.len(),
+            "singleflight: batch func must return one res per key"
+        );
+        for (send, res) in batch.sends.into_iter().zip(results) {
+            // the caller may have stopped waiting
+            let _ = send.send(res);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// CallContext is handed to funcs started through the `*_with_context`
+/// methods of [`Group`](crate::Group).
+///
+/// It is cancelled once nobody waits for the call anymore: every duplicate
+/// caller has given up and the caller that started the call has stopped
+/// waiting too. Long-running funcs can poll [`CallContext::is_cancelled`]
+/// and return early, as their res would not be received by anyone.
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CallContext {
+    // is_cancelled reports whether every caller has lost interest in the
+    // call.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
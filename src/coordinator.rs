@@ -0,0 +1,222 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// Coordinator elects a single execution call per key among processes that
+/// share it, so that only one process on a host executes a key at a time.
+///
+/// A [`Group`](crate::Group) configured with a coordinator acquires the key
+/// from it before running func. Keys and values cross process boundaries
+/// as bytes, see [`Codec`].
+pub trait Coordinator: Send + Sync {
+    /// Blocks until this process may execute key, or until another process
+    /// that executed key in the meantime published its value.
+    fn acquire(&self, key: &[u8]) -> io::Result<Acquired>;
+}
+
+/// Acquired is what a [`Coordinator`] hands a process that asked for a key.
+pub enum Acquired {
+    /// The process executes the key and publishes the value through the
+    /// lease. Dropping the lease unpublished lets the next process execute
+    /// the key.
+    Lead(Box<dyn Lease>),
+
+    /// Another process executed the key and published this value.
+    Done(Vec<u8>),
+}
+
+/// Lease is held by the process that executes a key.
+pub trait Lease: Send {
+    /// Hands val to the processes waiting for the key and releases it.
+    fn publish(self: Box<Self>, val: &[u8]) -> io::Result<()>;
+}
+
+/// Codec turns keys and values into bytes that can be shared with other
+/// processes, and back.
+pub trait Codec: Sized {
+    fn encode(&self) -> Vec<u8>;
+
+    /// Returns None if bytes do not hold a valid value.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Codec for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl Codec for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+macro_rules! int_codec {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                bytes.try_into().ok().map(<$int>::from_le_bytes)
+            }
+        }
+    )*};
+}
+
+int_codec!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// FileCoordinator coordinates the processes of a host through `flock`ed
+/// files in a shared directory.
+///
+/// The process that executes a key holds an exclusive lock on the key's
+/// lock file and writes the value next to it before unlocking. Processes
+/// that found the key locked wait for the lock and read the value.
+///
+/// Lock files cannot be removed while other processes may be waiting on
+/// them, so the directory keeps a lock file and the last value for every
+/// key hash it has seen, and grows with the number of distinct keys. It is
+/// up to its owner to clear it, e.g. when the processes restart.
+pub struct FileCoordinator {
+    dir: PathBuf,
+}
+
+impl FileCoordinator {
+    // new creates a coordinator over dir, creating the directory if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<FileCoordinator> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileCoordinator { dir })
+    }
+}
+
+impl Coordinator for FileCoordinator {
+    fn acquire(&self, key: &[u8]) -> io::Result<Acquired> {
+        let name = format!("{:016x}", fnv1a(key));
+        let lock = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.dir.join(format!("{name}.lock")))?;
+        let lease = FileLease {
+            lock,
+            path: self.dir.join(format!("{name}.val")),
+            key: key.to_vec(),
+        };
+
+        match lease.lock.try_lock() {
+            Ok(()) => {
+                // nobody executes the key, so a value left behind is stale
+                match fs::remove_file(&lease.path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => return Ok(Acquired::Lead(Box::new(lease))),
+                }
+            }
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+
+        // another process executes the key, wait for it to finish
+        lease.lock.lock()?;
+        match lease.read()? {
+            Some(val) => Ok(Acquired::Done(val)),
+            // it failed without a value, so this process executes the key
+            None => Ok(Acquired::Lead(Box::new(lease))),
+        }
+    }
+}
+
+// FileLease holds the lock on a key's lock file, which is released when the
+// file is closed
+struct FileLease {
+    lock: File,
+    path: PathBuf,
+    key: Vec<u8>,
+}
+
+impl FileLease {
+    // read returns the value published for the key, if any. Values are
+    // stored behind their key, since distinct keys may share a file name.
+    fn read(&self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = Vec::new();
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_end(&mut buf)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let Some((len, rest)) = buf.split_first_chunk::<8>() else {
+            return Ok(None);
+        };
+        let len = u64::from_le_bytes(*len) as usize;
+        match rest.split_at_checked(len) {
+            Some((key, val)) if key == self.key => Ok(Some(val.to_vec())),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Lease for FileLease {
+    fn publish(self: Box<Self>, val: &[u8]) -> io::Result<()> {
+        // a crashed process must not leave a partial value behind
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&(self.key.len() as u64).to_le_bytes())?;
+        file.write_all(&self.key)?;
+        file.write_all(val)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+// fnv1a hashes keys into file names that are the same in every process
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Coordinated runs the execution calls of a group through a coordinator
+pub(crate) struct Coordinated<K, T> {
+    pub(crate) coordinator: Box<dyn Coordinator>,
+    pub(crate) encode_key: fn(&K) -> Vec<u8>,
+    pub(crate) encode: fn(&T) -> Vec<u8>,
+    pub(crate) decode: fn(&[u8]) -> Option<T>,
+}
+
+impl<K, T> Coordinated<K, T> {
+    // run returns the value another process published for key, or runs
+    // func and publishes its value. Errors of the coordinator make func
+    // run uncoordinated.
+    pub(crate) fn run<E, F>(&self, key: &K, func: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let lease = match self.coordinator.acquire(&(self.encode_key)(key)) {
+            Ok(Acquired::Done(bytes)) => match (self.decode)(&bytes) {
+                Some(val) => return Ok(val),
+                None => None,
+            },
+            Ok(Acquired::Lead(lease)) => Some(lease),
+            Err(_) => None,
+        };
+
+        let res = func();
+        if let (Some(lease), Ok(val)) = (lease, &res) {
+            // the value is still good for this process
+            let _ = lease.publish(&(self.encode)(val));
+        }
+        res
+    }
+}
@@ -0,0 +1,147 @@
+use std::any::Any;
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+/// Error is returned to every caller of a call that did not produce a value.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// func returned an error. The same error is shared with the execution
+    /// call and all duplicate callers.
+    Func(Arc<E>),
+
+    /// func panicked while running in the execution call.
+    Panicked(Panicked),
+
+    /// The caller's deadline passed before the execution call finished.
+    Timeout,
+
+    /// The call for the key already had as many duplicate callers as the
+    /// group allows.
+    TooManyWaiters,
+
+    /// The group's executor dropped the execution call before it produced a
+    /// res.
+    Dropped,
+}
+
+impl<E> Error<E> {
+    // func_error returns the error returned by func, if any.
+    pub fn func_error(&self) -> Option<&E> {
+        match self {
+            Error::Func(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<E> Clone for Error<E> {
+    fn clone(&self) -> Self {
+        match self {
+            Error::Func(err) => Error::Func(err.clone()),
+            Error::Panicked(panicked) => Error::Panicked(panicked.clone()),
+            Error::Timeout => Error::Timeout,
+            Error::TooManyWaiters => Error::TooManyWaiters,
+            Error::Dropped => Error::Dropped,
+        }
+    }
+}
+
+impl<E> fmt::Display for Error<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Func(err) => err.fmt(f),
+            Error::Panicked(panicked) => panicked.fmt(f),
+            Error::Timeout => write!(f, "singleflight: timed out waiting for the call"),
+            Error::TooManyWaiters => {
+                write!(f, "singleflight: too many callers waiting for the call")
+            }
+            Error::Dropped => write!(f, "singleflight: executor dropped the execution call"),
+        }
+    }
+}
+
+impl<E> StdError for Error<E>
+where
+    E: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Func(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+impl Error<anyhow::Error> {
+    // into_anyhow converts the error into an anyhow::Error, keeping the
+    // func error's chain, so callers can bubble it up with
+    // map_err(Error::into_anyhow)?. anyhow::Error is not a std error, so
+    // Error<anyhow::Error> is not one either and ? cannot convert it.
+    pub fn into_anyhow(self) -> anyhow::Error {
+        match self {
+            Error::Func(err) => match Arc::try_unwrap(err) {
+                Ok(err) => err,
+                Err(err) => anyhow::Error::new(SharedAnyhow(err)),
+            },
+            Error::Panicked(panicked) => anyhow::Error::new(panicked),
+            Error::Timeout => anyhow::Error::new(Error::<Infallible>::Timeout),
+            Error::TooManyWaiters => anyhow::Error::new(Error::<Infallible>::TooManyWaiters),
+            Error::Dropped => anyhow::Error::new(Error::<Infallible>::Dropped),
+        }
+    }
+}
+
+// SharedAnyhow is a func error still shared with other callers, standing
+// in for it in the chain of an anyhow::Error
+#[derive(Debug)]
+struct SharedAnyhow(Arc<anyhow::Error>);
+
+impl fmt::Display for SharedAnyhow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for SharedAnyhow {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Panicked is the error duplicate callers receive when the execution call
+/// panicked while running func.
+#[derive(Debug, Clone)]
+pub struct Panicked {
+    message: String,
+}
+
+impl Panicked {
+    pub(crate) fn new(payload: &(dyn Any + Send)) -> Panicked {
+        let message = if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+        Panicked { message }
+    }
+
+    // message returns the message the execution call panicked with.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "singleflight: func panicked: {}", self.message)
+    }
+}
+
+impl StdError for Panicked {}
@@ -0,0 +1,28 @@
+/// Task is a unit of work handed to an [`Executor`].
+pub type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Executor runs the execution calls that [`Group::go_chan`] starts in the
+/// background.
+///
+/// Any `Fn(Task)` closure is an executor, so a thread pool can be plugged
+/// in with `group.executor(move |task| pool.execute(task))`. Groups spawn a
+/// new thread per execution call by default.
+///
+/// [`Group::go_chan`]: crate::Group::go_chan
+pub trait Executor: Send + Sync {
+    fn execute(&self, task: Task);
+}
+
+impl<F> Executor for F
+where
+    F: Fn(Task) + Send + Sync,
+{
+    fn execute(&self, task: Task) {
+        self(task)
+    }
+}
+
+// spawn is the executor groups use unless another one is configured
+pub(crate) fn spawn(task: Task) {
+    std::thread::spawn(task);
+}
@@ -0,0 +1,18 @@
+use std::time::Instant;
+
+/// InFlight describes a call in flight, as listed by
+/// [`Group::inflight`](crate::Group::inflight).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlight<K> {
+    pub key: K,
+
+    /// When the call was started.
+    pub started: Instant,
+
+    /// Duplicate callers waiting for the call.
+    pub dup: usize,
+
+    /// Name of the thread executing func, if it has one. Async calls report
+    /// the thread that started them.
+    pub leader: Option<String>,
+}
@@ -0,0 +1,13 @@
+/// JoinPolicy picks the call a caller joins once a key has as many calls in
+/// flight as [`Group::max_inflight_per_key`](crate::Group::max_inflight_per_key)
+/// allows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JoinPolicy {
+    /// Join the call that started first.
+    #[default]
+    Earliest,
+
+    /// Join the call with the fewest duplicate callers, the earliest one of
+    /// them on a tie.
+    LeastLoaded,
+}
@@ -1,234 +1,2164 @@
-use anyhow::{anyhow, Result};
-use crossbeam::{channel::Receiver, channel::Sender, thread};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::{pin, Pin};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use hashbrown::hash_map::DefaultHashBuilder;
 use hashbrown::HashMap;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, MutexGuard};
 
-type ShareSender<T> = Sender<Result<(T, bool)>>;
-type ShareReceiver<T> = Receiver<Result<(T, bool)>>;
+pub use batch::BatchGroup;
+pub use context::CallContext;
+pub use coordinator::{Acquired, Codec, Coordinator, FileCoordinator, Lease};
+pub use error::{Error, Panicked};
+pub use executor::{Executor, Task};
+pub use inflight::InFlight;
+pub use join::JoinPolicy;
+pub use overflow::Overflow;
+pub use served::Served;
+pub use stats::Stats;
+pub use stream::Subscription;
+#[cfg(feature = "tower")]
+pub use tower::{Singleflight, SingleflightLayer};
 
-// call is an in-flight or completed call
-struct Call<T>
-where
-    T: Default + Clone,
-{
-    dup: usize,
+mod batch;
+mod context;
+mod coordinator;
+mod error;
+mod executor;
+mod inflight;
+mod join;
+mod overflow;
+mod served;
+mod stats;
+mod stream;
+#[cfg(feature = "tower")]
+mod tower;
+mod trace;
+
+use coordinator::Coordinated;
+use stats::Counters;
+use stream::{StreamCall, StreamMap};
+
+type ShareSender<T, E> = Sender<Result<(T, bool), Error<E>>>;
+type ShareReceiver<T, E> = Receiver<Result<(T, bool), Error<E>>>;
+type WakerSlot = Arc<Mutex<Option<Waker>>>;
+type CallRef<T, E> = Arc<Mutex<Call<T, E>>>;
+
+// Entry is what a group keeps for a key
+enum Entry<T, E> {
+    // the calls in flight for the key, oldest first
+    Calls(Vec<CallRef<T, E>>),
 
-    // ShareSender for the execution call to send work res to other duplicate callers
-    // ShareReceiver for duplicate callers to receive execution call's res
-    chan: (ShareSender<T>, ShareReceiver<T>),
+    // the res of a completed call, served until it expires
+    Done(Done<T, E>),
 }
 
-impl<T> Clone for Call<T>
+// Found is what a caller looking up a key can make use of
+enum Found<'a, T, E> {
+    Done(&'a Done<T, E>),
+    Call(&'a CallRef<T, E>),
+
+    // the call to join has as many waiters as allowed
+    Full,
+}
+
+struct Done<T, E> {
+    res: Result<T, Error<E>>,
+    expires: Instant,
+
+    // consecutive calls of the key whose func returned an error
+    failures: u32,
+}
+
+impl<T, E> Done<T, E>
 where
-    T: Default + Clone,
+    T: Clone,
 {
+    // get hands the cached res to a caller. A cached value is shared by
+    // definition.
+    fn get(&self, counters: &Counters) -> Result<(T, bool), Error<E>> {
+        counters.suppressed.fetch_add(1, Ordering::Relaxed);
+        self.res.clone().map(|val| (val, true))
+    }
+
+    // stale returns the value of an expired res that expired less than
+    // window ago
+    fn stale(&self, window: Duration) -> Option<T> {
+        match &self.res {
+            Ok(val) if self.expires + window > Instant::now() => Some(val.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T, E> Done<T, E> {
+    // is_kept reports whether the res is still of use at now: until it
+    // expires, then while it can be served stale, or while its failures
+    // can still grow the backoff of the key
+    fn is_kept(&self, opts: &Options<E>, now: Instant) -> bool {
+        let keep = match (&self.res, opts.stale, &opts.error_backoff) {
+            (Ok(_), Some(window), _) => window,
+            (Err(_), _, Some(backoff)) => backoff.max,
+            _ => Duration::ZERO,
+        };
+        self.expires + keep > now
+    }
+}
+
+// Options are the settings of a group that execution calls need
+struct Options<E> {
+    ttl: Option<Duration>,
+    error_ttl: Option<Duration>,
+    error_backoff: Option<Backoff>,
+    takeover: Option<Takeover<E>>,
+    stale: Option<Duration>,
+    max_inflight: usize,
+    join: JoinPolicy,
+    max_waiters: usize,
+    overflow: Overflow,
+}
+
+impl<E> Default for Options<E> {
+    fn default() -> Self {
+        Options {
+            ttl: None,
+            error_ttl: None,
+            error_backoff: None,
+            takeover: None,
+            stale: None,
+            max_inflight: 1,
+            join: JoinPolicy::Earliest,
+            max_waiters: usize::MAX,
+            overflow: Overflow::Reject,
+        }
+    }
+}
+
+impl<E> Clone for Options<E> {
     fn clone(&self) -> Self {
-        Self {
-            chan: self.chan.clone(),
+        Options {
+            ttl: self.ttl,
+            error_ttl: self.error_ttl,
+            error_backoff: self.error_backoff,
+            takeover: self.takeover.clone(),
+            stale: self.stale,
+            max_inflight: self.max_inflight,
+            join: self.join,
+            max_waiters: self.max_waiters,
+            overflow: self.overflow,
+        }
+    }
+}
+
+impl<E> Options<E> {
+    // expiry returns until when res is served from the cache, if at all.
+    // failures counts the consecutive errors of the key up to res.
+    fn expiry<T>(&self, res: &Result<T, Error<E>>, failures: u32) -> Option<Instant> {
+        let ttl = match res {
+            Ok(_) => self.ttl,
+            Err(Error::Func(_)) => match &self.error_backoff {
+                Some(backoff) => Some(backoff.window(failures)),
+                None => self.error_ttl,
+            },
+            Err(_) => None,
+        };
+        ttl.map(|ttl| Instant::now() + ttl)
+    }
+}
+
+// Backoff is the window errors are kept for, growing with every
+// consecutive failure of a key
+#[derive(Clone, Copy)]
+struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    fn window(&self, failures: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(failures.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+type Retryable<E> = dyn Fn(&E) -> bool + Send + Sync;
+
+// Takeover is the policy for handing a call whose func failed to one of its
+// duplicate callers
+struct Takeover<E> {
+    max: usize,
+    retryable: Arc<Retryable<E>>,
+}
 
-            dup: self.dup,
+impl<E> Clone for Takeover<E> {
+    fn clone(&self) -> Self {
+        Takeover {
+            max: self.max,
+            retryable: self.retryable.clone(),
         }
     }
 }
 
-impl<T> Call<T>
-where
-    T: Default + Clone,
-{
-    fn new() -> Call<T> {
+// call is an in-flight or completed call
+struct Call<T, E> {
+    dup: usize,
+    started: Instant,
+
+    // the thread executing func, as far as the call knows
+    leader: Thread,
+
+    // duplicate callers waiting for the execution call's res
+    waiters: Vec<Waiter<T, E>>,
+    next_id: usize,
+
+    // whether the caller that started the call still waits for its res
+    leader_waiting: bool,
+
+    // set for calls whose func was handed a CallContext
+    ctx: Option<CallContext>,
+
+    // consecutive failures of the key before the call
+    failures: u32,
+
+    // the expired res the call refreshes
+    stale: Option<Done<T, E>>,
+
+    // the duplicate caller promoted to run its func after a retryable
+    // error, until it claims the call, with the error to hand out if it
+    // goes away before that
+    promoted: Option<(usize, Error<E>)>,
+    takeovers: usize,
+
+    // set once the call was given up without a caller executing it
+    ended: bool,
+
+    counters: Arc<Counters>,
+    trace: trace::Link,
+}
+
+impl<T, E> Call<T, E> {
+    fn new(counters: Arc<Counters>) -> Call<T, E> {
         Call {
-            chan: crossbeam::channel::unbounded(),
             dup: 0,
+            started: Instant::now(),
+            leader: thread::current(),
+            waiters: Vec::new(),
+            next_id: 0,
+            leader_waiting: true,
+            ctx: None,
+            failures: 0,
+            stale: None,
+            promoted: None,
+            takeovers: 0,
+            ended: false,
+            counters,
+            trace: trace::Link::default(),
+        }
+    }
+
+    // join registers a duplicate caller and returns its waiter id together
+    // with the receiver it gets the execution call's res from. Callers that
+    // can_take_over hold a func of their own to run if the call is handed
+    // to them. It returns None if the call is over since it was looked up,
+    // in which case the caller looks the key up again.
+    fn join(
+        &mut self,
+        waker: Option<WakerSlot>,
+        can_take_over: bool,
+    ) -> Option<(usize, ShareReceiver<T, E>)> {
+        if self.is_over() {
+            return None;
+        }
+        let (send, recv) = crossbeam::channel::bounded(1);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.dup += 1;
+        self.waiters.push(Waiter {
+            id,
+            send,
+            waker,
+            can_take_over,
+            rerun: None,
+        });
+        self.counters.suppressed.fetch_add(1, Ordering::Relaxed);
+        self.counters.waiters.fetch_add(1, Ordering::Relaxed);
+        Some((id, recv))
+    }
+
+    // leave unregisters a duplicate caller that stopped waiting. It is a
+    // no-op once the execution call has handed out its res. See pass_on for
+    // what it returns.
+    fn leave(&mut self, id: usize) -> Option<Error<E>> {
+        if let Some(i) = self.waiters.iter().position(|waiter| waiter.id == id) {
+            self.waiters.swap_remove(i);
+            self.dup -= 1;
+            self.counters.waiters.fetch_sub(1, Ordering::Relaxed);
+            self.check_interest();
+            None
+        } else if matches!(self.promoted, Some((promoted, _)) if promoted == id) {
+            self.pass_on()
+        } else {
+            None
+        }
+    }
+
+    // promote hands the call to a duplicate caller that can take it over
+    // after func returned err, unless max takeovers have been made
+    fn promote(&mut self, max: usize, err: Error<E>) -> bool {
+        if self.takeovers >= max || !self.promote_waiter(err) {
+            return false;
+        }
+        self.takeovers += 1;
+        true
+    }
+
+    fn promote_waiter(&mut self, err: Error<E>) -> bool {
+        let Some(i) = self.waiters.iter().position(|w| w.can_take_over) else {
+            return false;
+        };
+        let waiter = self.waiters.remove(i);
+        self.dup -= 1;
+        self.counters.waiters.fetch_sub(1, Ordering::Relaxed);
+        self.promoted = Some((waiter.id, err));
+        waiter.close();
+        true
+    }
+
+    // claim makes the duplicate caller id the execution call if it has been
+    // promoted
+    fn claim(&mut self, id: usize) -> bool {
+        if !matches!(self.promoted, Some((promoted, _)) if promoted == id) {
+            return false;
+        }
+        self.promoted = None;
+        self.leader = thread::current();
+        // the caller was counted as suppressed when it joined
+        self.counters.suppressed.fetch_sub(1, Ordering::Relaxed);
+        self.counters.executions.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    // pass_on promotes the next duplicate caller in place of one that went
+    // away before claiming the call. With none left, the call is over and
+    // pass_on returns the error it was promoted for, which the call is to
+    // be settled with.
+    fn pass_on(&mut self) -> Option<Error<E>> {
+        let (_, err) = self.promoted.take()?;
+        if self.promote_waiter(err.clone()) {
+            return None;
+        }
+        self.ended = true;
+        Some(err)
+    }
+
+    // end takes the duplicate callers once the execution call is over,
+    // returning whether there were any
+    fn end(&mut self) -> (bool, Vec<Waiter<T, E>>) {
+        let waiters = std::mem::take(&mut self.waiters);
+        self.counters
+            .waiters
+            .fetch_sub(waiters.len(), Ordering::Relaxed);
+        self.counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.trace.end(self.dup);
+        (self.dup > 0, waiters)
+    }
+
+    // is_waiting reports whether the duplicate caller id still waits for
+    // the execution call's res
+    fn is_waiting(&self, id: usize) -> bool {
+        self.waiters.iter().any(|waiter| waiter.id == id)
+    }
+
+    // hand_over keeps the func of the duplicate caller id, as what rerun
+    // makes of it and the caller's sender
+    fn hand_over(&mut self, id: usize, rerun: impl FnOnce(ShareSender<T, E>) -> Rerun) {
+        if let Some(waiter) = self.waiters.iter_mut().find(|waiter| waiter.id == id) {
+            waiter.rerun = Some(rerun(waiter.send.clone()));
+        }
+    }
+
+    // take_rerun makes a duplicate caller whose func was handed over the
+    // execution call, returning the task that runs its func
+    fn take_rerun(&mut self) -> Option<Task> {
+        let i = self
+            .waiters
+            .iter()
+            .position(|waiter| waiter.rerun.is_some())?;
+        let waiter = self.waiters.remove(i);
+        self.dup -= 1;
+        self.counters.waiters.fetch_sub(1, Ordering::Relaxed);
+        self.leader_waiting = true;
+        self.counters.suppressed.fetch_sub(1, Ordering::Relaxed);
+        self.counters.executions.fetch_add(1, Ordering::Relaxed);
+        waiter.rerun.map(|rerun| rerun())
+    }
+
+    // leader_leave records that the caller that started the call stopped
+    // waiting for its res
+    fn leader_leave(&mut self) {
+        self.leader_waiting = false;
+        self.check_interest();
+    }
+
+    // check_interest cancels the call once nobody waits for it anymore
+    fn check_interest(&self) {
+        if let (0, false, Some(ctx)) = (self.dup, self.leader_waiting, &self.ctx) {
+            ctx.cancel();
         }
     }
+
+    fn is_cancelled(&self) -> bool {
+        self.ctx.as_ref().is_some_and(CallContext::is_cancelled)
+    }
+
+    // is_over reports whether new callers must start a call of their own
+    fn is_over(&self) -> bool {
+        self.ended || self.is_cancelled()
+    }
 }
 
-/// Group represents a class of work and creates a space in which units of work
-/// can be executed with duplicate suppression.
-pub struct Group<T>
+// Waiter is a duplicate caller of a call
+struct Waiter<T, E> {
+    id: usize,
+
+    // ShareSender for the execution call to send work res to the duplicate caller
+    send: ShareSender<T, E>,
+
+    // set for async duplicate callers, woken once the res has been sent
+    waker: Option<WakerSlot>,
+
+    can_take_over: bool,
+
+    // set for go_chan callers, which nobody waits on: runs their func as
+    // the execution call and sends them its res if the async one they
+    // joined is dropped
+    rerun: Option<Rerun>,
+}
+
+// Rerun makes the task that runs the func a go_chan caller handed over. The
+// task is made only once it is to run, as a task dropped unrun abandons
+// its call.
+type Rerun = Box<dyn FnOnce() -> Task + Send>;
+
+impl<T, E> Waiter<T, E> {
+    fn send(self, res: Result<(T, bool), Error<E>>) {
+        // the duplicate caller may have stopped waiting
+        let _ = self.send.send(res);
+        self.wake();
+    }
+
+    // close disconnects the duplicate caller without a res
+    fn close(self) {
+        let Waiter { send, waker, .. } = self;
+        drop(send);
+        Waiter::<T, E>::wake_slot(waker);
+    }
+
+    fn wake(self) {
+        Waiter::<T, E>::wake_slot(self.waker);
+    }
+
+    fn wake_slot(waker: Option<WakerSlot>) {
+        if let Some(waker) = waker.and_then(|waker| waker.lock().take()) {
+            waker.wake();
+        }
+    }
+}
+
+// Flight is a caller's handle on a call started or joined by launch
+struct Flight<T, E> {
+    // None if the res was served from the cache, or if the caller did not
+    // join a call
+    call: Option<CallRef<T, E>>,
+
+    // waiter id of a duplicate caller, None for the execution call
+    waiter: Option<usize>,
+    recv: ShareReceiver<T, E>,
+    span: trace::Span,
+}
+
+// Wait is the future an async duplicate caller polls until the execution
+// call sends its res. It resolves to None if the execution call was dropped
+// before it finished, or if the call was handed to the duplicate caller.
+struct Wait<'a, K, Q, T, E>
 where
-    T: Default + Clone + Send,
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
 {
-    shared_chans: Mutex<HashMap<String, Call<T>>>,
+    group: &'a Group<K, T, E>,
+    key: &'a Q,
+    call: CallRef<T, E>,
+    id: usize,
+    recv: ShareReceiver<T, E>,
+    waker: WakerSlot,
 }
 
-impl<T> Default for Group<T>
+// Wait holds no pinned state, so callers can poll it by reference
+impl<K, Q, T, E> Unpin for Wait<'_, K, Q, T, E>
 where
-    T: Default + Clone + Send,
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
 {
-    fn default() -> Self {
-        Self::new()
+}
+
+impl<K, Q, T, E> Drop for Wait<'_, K, Q, T, E>
+where
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
+{
+    fn drop(&mut self) {
+        // a dropped duplicate caller no longer waits for the res
+        let group = self.group;
+        group
+            .shared
+            .leave(&group.opts, self.key, &self.call, self.id);
     }
 }
 
-impl<T> Group<T>
+impl<K, Q, T, E> Future for Wait<'_, K, Q, T, E>
 where
-    T: Default + Clone + Send,
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
 {
-    pub fn new() -> Group<T> {
-        Group {
-            shared_chans: Mutex::new(HashMap::new()),
+    type Output = Option<Result<(T, bool), Error<E>>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        use crossbeam::channel::TryRecvError;
+
+        match self.recv.try_recv() {
+            Ok(res) => return Poll::Ready(Some(res)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        *self.waker.lock() = Some(cx.waker().clone());
+
+        // the res may have been sent before the waker was registered
+        match self.recv.try_recv() {
+            Ok(res) => Poll::Ready(Some(res)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
         }
     }
+}
 
-    // go executes and returns the results of the given function, making
-    // sure that only one execution is in-flight for a given key at a
-    // time. If a duplicate comes in, the duplicate caller waits for the
-    // original to complete and receives the same results.
-    // The bool value indicates whether v was given to multiple callers.
-    pub fn go<F>(&self, key: &str, func: F) -> Result<(T, bool)>
-    where
-        F: Fn() -> Result<T>,
-    {
-        let mut share = self.shared_chans.lock();
+// Abandon removes the key of an async execution call whose future is
+// dropped before func finishes, waking duplicate callers so one of them
+// can take over. A go_chan caller that joined the call runs its func in
+// place of the dropped one instead.
+struct Abandon<'a, K, Q, T, E>
+where
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
+{
+    group: &'a Group<K, T, E>,
+    key: &'a Q,
+    call: &'a CallRef<T, E>,
+    armed: bool,
+}
 
-        if let Some(call) = share.get_mut(key) {
-            call.dup += 1;
-            let call = call.clone();
-            drop(share);
-            let res = call.chan.1.recv().unwrap();
-            return res;
+impl<K, Q, T, E> Drop for Abandon<'_, K, Q, T, E>
+where
+    K: Hash + Eq + Clone + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    T: Clone + Send,
+{
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
         }
 
-        let call = Call::new();
-        share.entry(key.to_string()).or_insert(call);
-        drop(share);
+        let rerun = self.call.lock().take_rerun();
+        if let Some(rerun) = rerun {
+            self.group.executor.execute(rerun);
+            return;
+        }
 
-        let func_res = func();
+        self.group.shared.abandon(self.key, self.call);
+    }
+}
 
-        let mut shared = self.shared_chans.lock();
-        let call = shared.remove(key).unwrap();
-        drop(shared);
+// Unrun abandons the call of a task that the executor dropped without
+// running it, so that its callers do not wait for it forever
+struct Unrun<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    shared: Arc<Shared<K, T, E>>,
+    key: K,
+    call: CallRef<T, E>,
+    armed: bool,
+}
 
-        for _ in 0..=call.dup {
-            let shared_value = match &func_res {
-                Result::Ok(val) => anyhow::Result::Ok((val.clone(), call.dup > 0)),
-                Result::Err(err) => Err(anyhow!(err.to_string())),
-            };
-            call.chan.0.send(shared_value).unwrap();
+impl<K, T, E> Drop for Unrun<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    fn drop(&mut self) {
+        if self.armed {
+            self.shared.abandon::<K>(&self.key, &self.call);
         }
+    }
+}
+
+type Shard<K, T, E> = Mutex<HashMap<K, Entry<T, E>>>;
+
+// Shared is the state of a group that execution calls started by go_chan
+// keep using after go_chan returns
+struct Shared<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    // keys are spread over independently locked shards by their hash
+    shards: Box<[Shard<K, T, E>]>,
+    hasher: DefaultHashBuilder,
+    counters: Arc<Counters>,
+}
 
-        call.chan.1.recv().unwrap()
+impl<K, T, E> Shared<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    fn new(shards: usize) -> Shared<K, T, E> {
+        Shared {
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+            hasher: DefaultHashBuilder::default(),
+            counters: Arc::default(),
+        }
     }
 
-    // DoChan is like Do but returns a channel that will receive the
-    // results when they are ready.
-    pub fn go_chan<F>(&self, key: &str, func: F) -> ShareReceiver<T>
+    // shard returns the shard that holds key
+    fn shard<Q>(&self, key: &Q) -> &Shard<K, T, E>
     where
-        F: Fn() -> Result<T>,
-        F: Sync,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
     {
-        let mut share = self.shared_chans.lock();
+        let hash = self.hasher.hash_one(key);
+        &self.shards[(hash % self.shards.len() as u64) as usize]
+    }
 
-        if let Some(call) = share.get_mut(key) {
-            call.dup += 1;
-            let call = call.clone();
-            drop(share);
-            let (shared_send, shared_recv) = crossbeam::channel::bounded(1);
-            thread::scope(|sco| {
-                sco.spawn(|_| {
-                    shared_send.send(call.chan.1.recv().unwrap()).unwrap();
-                });
-            })
-            .unwrap();
-            return shared_recv;
+    // lookup returns the unexpired res for key, or the call to join once
+    // the key has as many calls in flight as opts allow. Otherwise the
+    // caller starts a call of its own. Cancelled and ended calls are not
+    // counted, and neither are calls that have as many waiters as opts
+    // allow. The key is reported as Full if every call is.
+    fn lookup<'a, Q>(
+        opts: &Options<E>,
+        shared: &'a HashMap<K, Entry<T, E>>,
+        key: &Q,
+    ) -> Option<Found<'a, T, E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match shared.get(key)? {
+            Entry::Done(done) => (done.expires > Instant::now()).then_some(Found::Done(done)),
+            Entry::Calls(calls) => {
+                let live = calls.iter().filter(|call| !call.lock().is_over());
+                if live.clone().count() < opts.max_inflight {
+                    return None;
+                }
+                let mut open = live.filter(|call| call.lock().dup < opts.max_waiters);
+                let call = match opts.join {
+                    JoinPolicy::Earliest => open.next(),
+                    JoinPolicy::LeastLoaded => open.min_by_key(|call| call.lock().dup),
+                };
+                Some(call.map_or(Found::Full, Found::Call))
+            }
         }
+    }
 
-        let call = Call::new();
-        share.entry(key.to_string()).or_insert(call);
-        drop(share);
-
-        let (s, r): (ShareSender<T>, ShareReceiver<T>) = crossbeam::channel::bounded(1);
-
-        thread::scope(|sco| {
-            sco.spawn(|_| {
-                let func_res = func();
+    // in_flight reports whether a call for key is in flight
+    fn in_flight<Q>(shared: &HashMap<K, Entry<T, E>>, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        matches!(shared.get(key), Some(Entry::Calls(calls))
+            if calls.iter().any(|call| !call.lock().is_over()))
+    }
 
-                let mut shared = self.shared_chans.lock();
-                let call = shared.remove(key).unwrap();
-                drop(shared);
+    // start registers a new call for key next to the ones in flight and
+    // returns it to the execution call. The call takes over the failures
+    // and the stale res of the entries it replaces.
+    fn start<Q>(
+        &self,
+        opts: &Options<E>,
+        shared: &mut HashMap<K, Entry<T, E>>,
+        key: &Q,
+    ) -> CallRef<T, E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.counters.executions.fetch_add(1, Ordering::Relaxed);
+        self.counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        let call = CallRef::new(Mutex::new(Call::new(self.counters.clone())));
+        let mut c = call.lock();
 
-                for i in 0..=call.dup {
-                    let shared_value = match &func_res {
-                        Result::Ok(val) => anyhow::Result::Ok((val.clone(), call.dup > 0)),
-                        Result::Err(err) => Err(anyhow!(err.to_string())),
-                    };
-                    if i == call.dup {
-                        s.send(shared_value).unwrap();
-                    } else {
-                        call.chan.0.send(shared_value).unwrap();
-                    }
+        if let Some(Entry::Calls(calls)) = shared.get_mut(key) {
+            calls.retain(|prev| {
+                let mut prev = prev.lock();
+                c.failures = c.failures.max(prev.failures);
+                if !prev.is_over() {
+                    return true;
+                }
+                if c.stale.is_none() {
+                    c.stale = prev.stale.take();
                 }
+                false
             });
-        })
-        .unwrap();
+            calls.push(call.clone());
+        } else {
+            Self::sweep(opts, shared);
+            if let Some(Entry::Done(done)) =
+                shared.insert(key.to_owned(), Entry::Calls(vec![call.clone()]))
+            {
+                c.failures = done.failures;
+                c.stale = Some(done);
+            }
+        }
 
-        r
+        drop(c);
+        call
     }
-}
-
-#[cfg(test)]
-mod tests {
 
-    use super::Group;
-
-    const RES: usize = 7;
+    // sweep drops the kept res of every key of shared that is of no use
+    // anymore, so keys that are not called again do not stay forever. It
+    // runs when shared is full, and leaves room for as many keys as it
+    // kept, which spreads its cost over the keys inserted in between.
+    fn sweep(opts: &Options<E>, shared: &mut HashMap<K, Entry<T, E>>) {
+        if shared.len() < shared.capacity() {
+            return;
+        }
+        let now = Instant::now();
+        shared.retain(|_, entry| match entry {
+            Entry::Done(done) => done.is_kept(opts, now),
+            Entry::Calls(_) => true,
+        });
+        shared.reserve(shared.len());
+    }
 
-    #[test]
-    fn test_go_simple() {
-        let g = Group::new();
-        let res = g.go("key", || Ok(RES));
-        // simple call's result should not be shared
-        assert_eq!(res.unwrap(), (RES, false));
+    // task makes the task that runs the execution call of call for key on
+    // the executor and sends its res to send
+    fn task<R>(
+        self: &Arc<Self>,
+        key: K,
+        call: CallRef<T, E>,
+        send: ShareSender<T, E>,
+        run: R,
+    ) -> Task
+    where
+        K: Send + 'static,
+        R: FnOnce() -> Result<(T, bool), Error<E>> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let unrun = Unrun {
+            shared: self.clone(),
+            key,
+            call,
+            armed: true,
+        };
+        Box::new(move || {
+            let mut unrun = unrun;
+            unrun.armed = false;
+            // the caller may have dropped the receiver
+            let _ = send.send(run());
+        })
     }
 
-    #[test]
-    fn test_go_chan_simple() {
-        let g = Group::new();
-        let ch = g.go_chan("key", || Ok(RES));
-        assert_eq!(ch.capacity().unwrap(), 1);
-        // simple call's result should not be shared
-        assert_eq!(ch.recv().unwrap().unwrap(), (RES, false));
+    // abandon removes call from key and closes the channels of its
+    // duplicate callers, which look the key up again
+    fn abandon<Q>(&self, key: &Q, call: &CallRef<T, E>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key, call, None);
+        let (_, waiters) = call.lock().end();
+        for waiter in waiters {
+            waiter.close();
+        }
     }
 
-    #[test]
-    fn test_go_multiple_threads() {
-        use std::time::Duration;
+    // remove removes call from key unless the key has been forgotten or
+    // already belongs to newer calls. A done res is cached in place of the
+    // calls of the key, leaving the others in flight to finish uncached.
+    fn remove<Q>(&self, key: &Q, call: &CallRef<T, E>, done: Option<Done<T, E>>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut shared = self.shard(key).lock();
+        let Some(entry) = shared.get_mut(key) else {
+            return;
+        };
+        let Entry::Calls(calls) = entry else {
+            return;
+        };
+        let Some(i) = calls.iter().position(|cur| Arc::ptr_eq(cur, call)) else {
+            return;
+        };
 
-        use crossbeam::thread;
+        calls.remove(i);
+        match done {
+            Some(done) => *entry = Entry::Done(done),
+            None if calls.is_empty() => {
+                shared.remove(key);
+            }
+            None => {}
+        }
+    }
 
-        fn expensive_fn() -> anyhow::Result<usize> {
-            std::thread::sleep(Duration::new(0, 500));
-            Ok(RES)
+    // finish removes the call for key and hands func_res to every duplicate
+    // caller, returning the execution call's own res.
+    fn finish<Q>(
+        &self,
+        opts: &Options<E>,
+        key: &Q,
+        call: &CallRef<T, E>,
+        func_res: Result<T, E>,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let func_res = func_res.map_err(|err| Error::Func(Arc::new(err)));
+        if func_res.is_err() {
+            self.counters.errors.fetch_add(1, Ordering::Relaxed);
         }
 
-        let g = Group::new();
-        thread::scope(|s| {
-            for _ in 0..10 {
-                s.spawn(|_| {
-                    let res = g.go("key", expensive_fn);
-                    // mutiple call's result may be shared by ohter duplicate calls
-                    assert_eq!(res.unwrap().0, RES);
-                });
+        // a retryable error is handed to the caller whose func failed only,
+        // while a duplicate caller runs its func for the others
+        if let (Err(err @ Error::Func(func_err)), Some(takeover)) = (&func_res, &opts.takeover) {
+            if (takeover.retryable)(func_err) && call.lock().promote(takeover.max, err.clone()) {
+                return Err(err.clone());
             }
-        })
-        .unwrap();
-    }
-
-    #[test]
-    fn test_go_chan_mutiple_threads() {
-        use std::time::Duration;
+        }
 
-        use crossbeam::thread;
+        self.settle(opts, key, call, func_res)
+    }
 
-        fn expensive_fn() -> anyhow::Result<usize> {
-            std::thread::sleep(Duration::new(0, 500));
-            Ok(RES)
+    // leave unregisters the duplicate caller id of the call for key, and
+    // settles the call if the caller was the last one it could be handed to
+    fn leave<Q>(&self, opts: &Options<E>, key: &Q, call: &CallRef<T, E>, id: usize)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let err = call.lock().leave(id);
+        if let Some(err) = err {
+            let _ = self.settle(opts, key, call, Err(err));
         }
+    }
 
-        let g = Group::new();
-        thread::scope(|s| {
-            for _ in 0..10 {
+    // settle removes the call for key, keeping res as opts say, and hands
+    // res to every duplicate caller, returning the execution call's own res
+    fn settle<Q>(
+        &self,
+        opts: &Options<E>,
+        key: &Q,
+        call: &CallRef<T, E>,
+        func_res: Result<T, Error<E>>,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // the res of a cancelled call is whatever func bailed out with, so it
+        // is neither kept nor counted as a failure of the key
+        let (cancelled, failures) = {
+            let call = call.lock();
+            (call.is_cancelled(), call.failures)
+        };
+        let done = if cancelled {
+            None
+        } else {
+            let failures = match func_res {
+                Ok(_) => 0,
+                Err(_) => failures.saturating_add(1),
+            };
+            opts.expiry(&func_res, failures).map(|expires| Done {
+                res: func_res.clone(),
+                expires,
+                failures,
+            })
+        };
+        self.remove(key, call, done);
+        let (shared, waiters) = call.lock().end();
+
+        for waiter in waiters {
+            let shared_value = match &func_res {
+                Ok(val) => Ok((val.clone(), shared)),
+                Err(err) => Err(err.clone()),
+            };
+            waiter.send(shared_value);
+        }
+
+        func_res.map(|val| (val, shared))
+    }
+
+    // panicked removes the call for key after func panicked and hands every
+    // duplicate caller a Panicked error, leaving the key free for the next
+    // caller.
+    fn panicked<Q>(&self, key: &Q, call: &CallRef<T, E>, payload: &(dyn Any + Send)) -> Panicked
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let panicked = Panicked::new(payload);
+        self.counters.panics.fetch_add(1, Ordering::Relaxed);
+
+        self.remove(key, call, None);
+        let (_, waiters) = call.lock().end();
+
+        for waiter in waiters {
+            waiter.send(Err(Error::Panicked(panicked.clone())));
+        }
+
+        panicked
+    }
+
+    // bypass hands a caller the res of the func it ran on its own, without
+    // joining the call for its key
+    fn bypass(&self, func_res: Result<T, E>) -> Result<(T, bool), Error<E>> {
+        self.counters.executions.fetch_add(1, Ordering::Relaxed);
+        func_res.map(|val| (val, false)).map_err(|err| {
+            self.counters.errors.fetch_add(1, Ordering::Relaxed);
+            Error::Func(Arc::new(err))
+        })
+    }
+}
+
+// default_shards is the number of shards of a group unless set otherwise
+fn default_shards() -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+    (cpus * 4).next_power_of_two()
+}
+
+/// Group represents a class of work and creates a space in which units of work
+/// can be executed with duplicate suppression.
+///
+/// Units of work are identified by keys of type `K`. Methods look keys up
+/// through [`Borrow`], so a `Group<String, T>` can be called with `&str`
+/// without allocating unless a new call has to be registered.
+///
+/// Errors returned by func are of type `E` and are shared with every caller
+/// of the call as an [`Arc<E>`], so their type and source chain survive.
+///
+/// Values are cloned for every caller of a call. Large values, or values
+/// that are not `Clone`, can be shared through an [`ArcGroup`] instead.
+pub struct Group<K, T, E = anyhow::Error>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    shared: Arc<Shared<K, T, E>>,
+    opts: Options<E>,
+    propagate_panics: bool,
+    executor: Arc<dyn Executor>,
+    coordinated: Option<Arc<Coordinated<K, T>>>,
+    key_fmt: trace::KeyFmt<K>,
+    streams: Arc<StreamMap<K, T, E>>,
+}
+
+impl<K, T, E> Default for Group<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    fn default() -> Self {
+        Group {
+            shared: Arc::new(Shared::new(default_shards())),
+            opts: Options::default(),
+            propagate_panics: false,
+            executor: Arc::new(executor::spawn),
+            coordinated: None,
+            key_fmt: trace::KeyFmt::default(),
+            streams: Arc::default(),
+        }
+    }
+}
+
+impl<K, T> Group<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    // new creates a group whose funcs return anyhow errors. Groups with
+    // another error type are created with Group::default.
+    pub fn new() -> Group<K, T> {
+        Self::default()
+    }
+}
+
+impl<K, T, E> Group<K, T, E>
+where
+    K: Hash + Eq + Clone,
+    T: Clone + Send,
+{
+    // propagate_panics makes duplicate callers of go and go_async re-raise
+    // a panic of the execution call instead of returning a Panicked error.
+    // The caller whose func panicked always re-raises its panic, even if
+    // func ran on the executor, except for go_chan, whose receiver gets the
+    // Panicked error. Subscriptions of go_stream re-raise a panic of the
+    // stream's func too.
+    pub fn propagate_panics(mut self, propagate: bool) -> Self {
+        self.propagate_panics = propagate;
+        self
+    }
+
+    // executor sets the executor go_chan runs execution calls on.
+    pub fn executor<X>(mut self, executor: X) -> Self
+    where
+        X: Executor + 'static,
+    {
+        self.executor = Arc::new(executor);
+        self
+    }
+
+    // ttl makes the group keep the value of a completed call for ttl. Until
+    // it expires, callers of the key get the value right away instead of
+    // starting a new call. Use forget to drop a value early. Expired values
+    // are dropped when their key is called next, or as calls for new keys
+    // come in.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.opts.ttl = Some(ttl);
+        self
+    }
+
+    // error_ttl is like ttl for the errors returned by func. Panics are
+    // never kept.
+    pub fn error_ttl(mut self, ttl: Duration) -> Self {
+        self.opts.error_ttl = Some(ttl);
+        self
+    }
+
+    // error_backoff is like error_ttl, but keeps an error for a window that
+    // starts at initial and doubles with every consecutive error of the
+    // key, up to max. A call that returns a value resets the window, and so
+    // does forget, or an error that expired more than max ago. It takes
+    // precedence over error_ttl.
+    pub fn error_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.opts.error_backoff = Some(Backoff { initial, max });
+        self
+    }
+
+    // takeover makes a call whose func returned an error that retryable
+    // accepts run again for its duplicate callers: one caller of go or
+    // go_async waiting for the call is promoted to run its own func, while
+    // the others keep waiting. The caller whose func failed gets the error.
+    // A call is taken over at most max times, after which its error is
+    // handed to every caller.
+    pub fn takeover<R>(mut self, max: usize, retryable: R) -> Self
+    where
+        R: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.opts.takeover = Some(Takeover {
+            max,
+            retryable: Arc::new(retryable),
+        });
+        self
+    }
+
+    // stale_while_revalidate makes go_stale serve values for window after
+    // they expired, while a call refreshes them. It only matters for
+    // groups that keep values, see ttl.
+    pub fn stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.opts.stale = Some(window);
+        self
+    }
+
+    // max_inflight_per_key lets up to max calls per key execute at once.
+    // Callers beyond max join one of them as picked by join. Strict
+    // singleflight, the default, is a max of 1.
+    pub fn max_inflight_per_key(mut self, max: usize, join: JoinPolicy) -> Self {
+        self.opts.max_inflight = max.max(1);
+        self.opts.join = join;
+        self
+    }
+
+    // max_waiters_per_key caps the duplicate callers a call waits for at
+    // max, so that callers cannot pile up behind a call that hangs. What
+    // happens to further callers is up to overflow. Calls are unbounded by
+    // default.
+    pub fn max_waiters_per_key(mut self, max: usize, overflow: Overflow) -> Self {
+        self.opts.max_waiters = max;
+        self.opts.overflow = overflow;
+        self
+    }
+
+    // shards sets the number of independently locked maps the group spreads
+    // keys over, so that calls for unrelated keys rarely contend. It must be
+    // set before the group is used. The default scales with the number of
+    // CPUs.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shared = Arc::new(Shared::new(shards));
+        self
+    }
+
+    // coordinator makes the group acquire the key of an execution call from
+    // coordinator before running func, so that processes sharing the
+    // coordinator execute a key once between them. A process that waited
+    // for another one gets the value it published, decoded with Codec.
+    // Errors are not shared: a process whose func failed lets the next one
+    // execute the key. go_async and go_many do not consult the coordinator,
+    // as they must not block on other processes.
+    pub fn coordinator<C>(mut self, coordinator: C) -> Self
+    where
+        C: Coordinator + 'static,
+        K: Codec,
+        T: Codec,
+    {
+        self.coordinated = Some(Arc::new(Coordinated {
+            coordinator: Box::new(coordinator),
+            encode_key: K::encode,
+            encode: T::encode,
+            decode: T::decode,
+        }));
+        self
+    }
+
+    // trace_keys_with makes the spans of the group's callers carry their
+    // key, rendered by f. Spans leave keys out by default, since keys need
+    // not implement Debug and may be sensitive.
+    #[cfg(feature = "tracing")]
+    pub fn trace_keys_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K) -> String + Send + Sync + 'static,
+    {
+        self.key_fmt = trace::KeyFmt::new(f);
+        self
+    }
+
+    // stats returns a snapshot of the group's counters.
+    pub fn stats(&self) -> Stats {
+        self.shared.counters.snapshot()
+    }
+
+    // inflight returns a snapshot of the calls in flight, for debugging
+    // calls that hang. Keys with several calls in flight are listed once
+    // per call. Streams are not listed.
+    pub fn inflight(&self) -> Vec<InFlight<K>> {
+        let mut inflight = Vec::new();
+        for shard in self.shared.shards.iter() {
+            for (key, entry) in shard.lock().iter() {
+                let Entry::Calls(calls) = entry else {
+                    continue;
+                };
+                for call in calls {
+                    let call = call.lock();
+                    if call.is_over() {
+                        continue;
+                    }
+                    inflight.push(InFlight {
+                        key: key.clone(),
+                        started: call.started,
+                        dup: call.dup,
+                        leader: call.leader.name().map(String::from),
+                    });
+                }
+            }
+        }
+        inflight
+    }
+
+    // go executes and returns the results of the given function, making
+    // sure that only one execution is in-flight for a given key at a
+    // time. If a duplicate comes in, the duplicate caller waits for the
+    // original to complete and receives the same results.
+    // The bool value indicates whether v was given to multiple callers.
+    pub fn go<Q, F>(&self, key: &Q, func: F) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<T, E>,
+    {
+        self.shared.counters.calls.fetch_add(1, Ordering::Relaxed);
+        let span = trace::Span::new("go", || self.key_fmt.format(key));
+        let call = loop {
+            let mut share = self.shared.shard(key).lock();
+            let call = match Shared::lookup(&self.opts, &share, key) {
+                Some(Found::Done(done)) => {
+                    span.cached();
+                    return done.get(&self.shared.counters);
+                }
+                Some(Found::Call(call)) => call.clone(),
+                Some(Found::Full) => {
+                    drop(share);
+                    return match self.opts.overflow {
+                        Overflow::Reject => Err(Error::TooManyWaiters),
+                        Overflow::Run => self.shared.bypass(func()),
+                    };
+                }
+                None => {
+                    let call = self.shared.start(&self.opts, &mut share, key);
+                    span.lead(&mut call.lock().trace);
+                    break call;
+                }
+            };
+
+            let mut c = call.lock();
+            let Some((id, recv)) = c.join(None, true) else {
+                continue;
+            };
+            span.follow(&c.trace, c.dup);
+            drop(c);
+            drop(share);
+            match recv.recv() {
+                Ok(res) => {
+                    span.received();
+                    return self.check_panicked(res);
+                }
+                // the call was handed to this caller
+                Err(_) if call.lock().claim(id) => {
+                    span.lead(&mut call.lock().trace);
+                    break call;
+                }
+                // the async execution call was dropped
+                Err(_) => continue,
+            }
+        };
+
+        let func = || match &self.coordinated {
+            Some(coordinated) => coordinated.run(&key.to_owned(), func),
+            None => func(),
+        };
+        let func_res = match span.in_scope(|| panic::catch_unwind(AssertUnwindSafe(func))) {
+            Ok(func_res) => func_res,
+            Err(payload) => {
+                self.shared.panicked(key, &call, &*payload);
+                panic::resume_unwind(payload);
+            }
+        };
+
+        self.shared.finish(&self.opts, key, &call, func_res)
+    }
+
+    // go_many is like go for a batch of keys. Keys that have a call in
+    // flight join it, and the caller becomes the execution call of all
+    // others. func loads those keys at once and returns one res per key,
+    // in the order they are given. go_many returns the res of every key.
+    // Keys whose call is dropped before it hands out a res are looked up
+    // again, so func may be called more than once.
+    pub fn go_many<I, F>(
+        &self,
+        keys: I,
+        mut func: F,
+    ) -> std::collections::HashMap<K, Result<(T, bool), Error<E>>>
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&[K]) -> Vec<Result<T, E>>,
+    {
+        let mut res = std::collections::HashMap::new();
+        let mut seen = hashbrown::HashSet::new();
+        let mut keys: Vec<K> = keys
+            .into_iter()
+            .filter(|key| seen.insert(key.clone()))
+            .collect();
+        self.shared
+            .counters
+            .calls
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+
+        while !keys.is_empty() {
+            keys = self.go_many_round(keys, &mut func, &mut res);
+        }
+        res
+    }
+
+    // go_many_round is one round of go_many over keys, returning the keys
+    // whose call was dropped without a res
+    fn go_many_round<F>(
+        &self,
+        keys: Vec<K>,
+        func: &mut F,
+        res: &mut std::collections::HashMap<K, Result<(T, bool), Error<E>>>,
+    ) -> Vec<K>
+    where
+        F: FnMut(&[K]) -> Vec<Result<T, E>>,
+    {
+        let mut led = Vec::new();
+        let mut joined = Vec::new();
+
+        for key in keys {
+            let mut share = self.shared.shard(&key).lock();
+            loop {
+                match Shared::lookup(&self.opts, &share, &key) {
+                    Some(Found::Done(done)) => {
+                        let done = done.get(&self.shared.counters);
+                        res.insert(key, done);
+                    }
+                    Some(Found::Call(call)) => {
+                        let Some((id, recv)) = call.lock().join(None, false) else {
+                            continue;
+                        };
+                        joined.push((key, call.clone(), id, recv));
+                    }
+                    Some(Found::Full) => match self.opts.overflow {
+                        Overflow::Reject => {
+                            res.insert(key, Err(Error::TooManyWaiters));
+                        }
+                        Overflow::Run => led.push((key, None)),
+                    },
+                    None => {
+                        let call = self.shared.start(&self.opts, &mut share, &key);
+                        led.push((key, Some(call)));
+                    }
+                }
+                break;
+            }
+        }
+
+        if !led.is_empty() {
+            let led_keys: Vec<K> = led.iter().map(|(key, _)| key.clone()).collect();
+            let func_res = panic::catch_unwind(AssertUnwindSafe(|| {
+                let func_res = func(&led_keys);
+                assert_eq!(
+                    func_res.len(),
+                    led_keys.len(),
+                    "singleflight: go_many func must return one res per key"
+                );
+                func_res
+            }));
+
+            match func_res {
+                Ok(func_res) => {
+                    for ((key, call), func_res) in led.into_iter().zip(func_res) {
+                        let key_res = match call {
+                            Some(call) => self.shared.finish(&self.opts, &key, &call, func_res),
+                            None => self.shared.bypass(func_res),
+                        };
+                        res.insert(key, key_res);
+                    }
+                }
+                Err(payload) => {
+                    for (key, call) in &led {
+                        if let Some(call) = call {
+                            self.shared.panicked(key, call, &*payload);
+                        }
+                    }
+                    // the calls joined are no longer waited for
+                    for (key, call, id, _) in &joined {
+                        self.shared.leave(&self.opts, key, call, *id);
+                    }
+                    panic::resume_unwind(payload);
+                }
+            }
+        }
+
+        // wait only after running func, so that callers leading each other's
+        // keys cannot deadlock
+        let mut dropped = Vec::new();
+        for (key, _, _, recv) in joined {
+            match recv.recv() {
+                Ok(key_res) => {
+                    res.insert(key, self.check_panicked(key_res));
+                }
+                // the async execution call was dropped
+                Err(_) => dropped.push(key),
+            }
+        }
+        dropped
+    }
+
+    // go_async is the async counterpart of go. Duplicate callers are
+    // suspended until the original completes instead of blocking their
+    // thread, so it can be used from any executor.
+    // If the original caller's future is dropped before func finishes, one
+    // of the duplicate callers takes over and runs its own func.
+    pub async fn go_async<Q, F, Fut>(&self, key: &Q, func: F) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.shared.counters.calls.fetch_add(1, Ordering::Relaxed);
+        loop {
+            let role = {
+                let mut share = self.shared.shard(key).lock();
+                match Shared::lookup(&self.opts, &share, key) {
+                    Some(Found::Done(done)) => return done.get(&self.shared.counters),
+                    Some(Found::Call(call)) => {
+                        let waker = WakerSlot::default();
+                        let Some((id, recv)) = call.lock().join(Some(waker.clone()), true) else {
+                            continue;
+                        };
+                        Some(Err(Wait {
+                            group: self,
+                            key,
+                            call: call.clone(),
+                            id,
+                            recv,
+                            waker,
+                        }))
+                    }
+                    Some(Found::Full) => match self.opts.overflow {
+                        Overflow::Reject => return Err(Error::TooManyWaiters),
+                        Overflow::Run => None,
+                    },
+                    None => Some(Ok(self.shared.start(&self.opts, &mut share, key))),
+                }
+            };
+
+            // the call for the key is full, so the caller runs on its own
+            let Some(role) = role else {
+                return self.shared.bypass(func().await);
+            };
+            let call = match role {
+                Ok(call) => call,
+                Err(mut wait) => match (&mut wait).await {
+                    Some(res) => return self.check_panicked(res),
+                    // the call was handed to this caller
+                    None if wait.call.lock().claim(wait.id) => wait.call.clone(),
+                    None => continue,
+                },
+            };
+
+            let mut abandon = Abandon {
+                group: self,
+                key,
+                call: &call,
+                armed: true,
+            };
+            let mut fut = pin!(func());
+            let func_res = std::future::poll_fn(|cx| {
+                match panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+                    Ok(Poll::Pending) => Poll::Pending,
+                    Ok(Poll::Ready(func_res)) => Poll::Ready(Ok(func_res)),
+                    Err(payload) => Poll::Ready(Err(payload)),
+                }
+            })
+            .await;
+            abandon.armed = false;
+
+            return match func_res {
+                Ok(func_res) => self.shared.finish(&self.opts, key, &call, func_res),
+                Err(payload) => {
+                    self.shared.panicked(key, &call, &*payload);
+                    panic::resume_unwind(payload);
+                }
+            };
+        }
+    }
+
+    // forget tells the group to forget about a key. Future calls for the key
+    // execute func rather than waiting for an earlier call to complete. The
+    // earlier call still hands its res to the callers that already joined it.
+    // The same goes for a stream of the key and its subscribers.
+    pub fn forget<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shared.shard(key).lock().remove(key);
+        self.streams.lock().remove(key);
+    }
+
+    // check_panicked re-raises a panic of the execution call in a duplicate
+    // caller if the group propagates panics.
+    fn check_panicked(&self, res: Result<(T, bool), Error<E>>) -> Result<(T, bool), Error<E>> {
+        if let (true, Err(Error::Panicked(panicked))) = (self.propagate_panics, &res) {
+            panic::resume_unwind(Box::new(panicked.clone()));
+        }
+        res
+    }
+
+    // check_received is check_panicked for the res flight received. The
+    // caller whose func ran on the executor re-raises its panic, as the
+    // execution call of go does.
+    fn check_received(
+        &self,
+        flight: &Flight<T, E>,
+        res: Result<(T, bool), Error<E>>,
+    ) -> Result<(T, bool), Error<E>> {
+        if let (None, Err(Error::Panicked(panicked))) = (flight.waiter, &res) {
+            panic::resume_unwind(Box::new(panicked.clone()));
+        }
+        self.check_panicked(res)
+    }
+
+    // go_chan is like go but returns a channel that will receive the
+    // results when they are ready. It returns immediately: the execution
+    // call runs func on the group's executor, so the receiver can be used
+    // in select! alongside timeouts and other channels. A caller that joins
+    // a call of go_async keeps func to run it on the executor should that
+    // call be dropped, so the receiver gets a res either way. It is
+    // disconnected only if the executor drops the execution call.
+    pub fn go_chan<Q, F>(&self, key: &Q, func: F) -> ShareReceiver<T, E>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.shared.counters.calls.fetch_add(1, Ordering::Relaxed);
+        let span = trace::Span::new("go_chan", || self.key_fmt.format(key));
+        let mut func = Some(move |_| func());
+        loop {
+            let flight = self.launch(key, span.clone(), false, &mut func);
+            let (Some(call), Some(id), Some(f)) = (&flight.call, flight.waiter, func.take()) else {
+                return flight.recv;
+            };
+
+            let mut c = call.lock();
+            if c.is_waiting(id) {
+                let ctx = c.ctx.clone().unwrap_or_default();
+                let run = self.execution(key.to_owned(), call.clone(), ctx, flight.span.clone(), f);
+                let (shared, owned, rerun_call) =
+                    (self.shared.clone(), key.to_owned(), call.clone());
+                c.hand_over(id, move |send| {
+                    Box::new(move || shared.task(owned, rerun_call, send, run))
+                });
+                return flight.recv;
+            }
+            if !flight.recv.is_empty() {
+                return flight.recv;
+            }
+            // the call was dropped before func could be handed over
+            func = Some(f);
+        }
+    }
+
+    // go_timeout is like go but waits at most timeout for the res. See
+    // go_deadline.
+    pub fn go_timeout<Q, F>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+        func: F,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let deadline = Instant::now() + timeout;
+        self.launch_deadline("go_timeout", key, false, deadline, |_| func())
+    }
+
+    // go_deadline is like go but gives up waiting with a Timeout error once
+    // deadline has passed. Like go_chan, the execution call runs func on the
+    // group's executor, so it keeps running for the other callers of the
+    // key when this caller gives up. If the executor drops the execution
+    // call, the caller that started it gets a Dropped error.
+    pub fn go_deadline<Q, F>(
+        &self,
+        key: &Q,
+        deadline: Instant,
+        func: F,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.launch_deadline("go_deadline", key, false, deadline, |_| func())
+    }
+
+    // go_timeout_with_context is like go_timeout but hands func a
+    // CallContext. See go_deadline_with_context.
+    pub fn go_timeout_with_context<Q, F>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+        func: F,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let deadline = Instant::now() + timeout;
+        self.launch_deadline("go_timeout_with_context", key, true, deadline, func)
+    }
+
+    // go_deadline_with_context is like go_deadline but hands func a
+    // CallContext that is cancelled once every caller of the call gave up
+    // waiting. Callers of go and go_chan never give up, so a call they
+    // joined is not cancelled. New callers of a cancelled call's key start
+    // a fresh call.
+    pub fn go_deadline_with_context<Q, F>(
+        &self,
+        key: &Q,
+        deadline: Instant,
+        func: F,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.launch_deadline("go_deadline_with_context", key, true, deadline, func)
+    }
+
+    // go_stale waits for the res of the call for key like go, but hands out
+    // a value that expired less than the group's stale_while_revalidate
+    // window ago right away, flagged as stale. The first caller served a
+    // stale value starts a call refreshing the key on the group's executor,
+    // and callers get the refreshed value once it completes. A refresh that
+    // fails drops the stale value. Like go_deadline, a caller whose call the
+    // executor drops gets a Dropped error.
+    pub fn go_stale<Q, F>(&self, key: &Q, func: F) -> Result<Served<T>, Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.shared.counters.calls.fetch_add(1, Ordering::Relaxed);
+        let span = trace::Span::new("go_stale", || self.key_fmt.format(key));
+        let mut func = Some(move |_| func());
+        loop {
+            let share = self.shared.shard(key).lock();
+            let stale = match (self.opts.stale, share.get(key)) {
+                (Some(window), Some(Entry::Done(done))) if done.expires <= Instant::now() => {
+                    done.stale(window)
+                }
+                (Some(window), Some(Entry::Calls(calls))) => calls.iter().find_map(|call| {
+                    call.lock()
+                        .stale
+                        .as_ref()
+                        .and_then(|done| done.stale(window))
+                }),
+                _ => None,
+            };
+            let Some(val) = stale else {
+                let flight = self.launch_locked(share, key, span.clone(), false, &mut func);
+                let res = match flight.recv.recv() {
+                    Ok(res) => res,
+                    // the async execution call was dropped
+                    Err(_) if flight.waiter.is_some() => continue,
+                    Err(_) => return Err(Error::Dropped),
+                };
+                flight.span.received();
+                let (val, shared) = self.check_received(&flight, res)?;
+                return Ok(Served {
+                    val,
+                    shared,
+                    stale: false,
+                });
+            };
+
+            if Shared::in_flight(&share, key) {
+                // the key is being refreshed already
+                self.shared
+                    .counters
+                    .suppressed
+                    .fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.launch_locked(share, key, span.clone(), false, &mut func);
+            }
+            span.stale();
+            return Ok(Served {
+                val,
+                shared: true,
+                stale: true,
+            });
+        }
+    }
+
+    // go_stream subscribes to the stream of values for key. The first caller
+    // of a key runs func on the group's executor and its items are handed to
+    // every subscriber of the call: the items produced before a caller
+    // joined are replayed to it, then it follows the live ones. An item that
+    // is an error ends the stream with that error for every subscriber.
+    // Streams are not kept once they end, and do not share calls with go;
+    // forget makes later callers of the key start a new stream.
+    pub fn go_stream<Q, F, I>(&self, key: &Q, func: F) -> Subscription<T, E>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> I + Send + 'static,
+        I: IntoIterator<Item = Result<T, E>>,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let counters = &self.shared.counters;
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        let mut streams = self.streams.lock();
+        if let Some(call) = streams.get(key) {
+            counters.suppressed.fetch_add(1, Ordering::Relaxed);
+            return Subscription::new(call.clone(), self.propagate_panics);
+        }
+
+        counters.executions.fetch_add(1, Ordering::Relaxed);
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        let call = Arc::new(StreamCall::new());
+        streams.insert(key.to_owned(), call.clone());
+        drop(streams);
+
+        let key = key.to_owned();
+        let streams = self.streams.clone();
+        let counters = self.shared.counters.clone();
+        let producer = call.clone();
+        self.executor.execute(Box::new(move || {
+            let func_res = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), E> {
+                for item in func() {
+                    producer.push(item?);
+                }
+                Ok(())
+            }));
+            let end = match func_res {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(err)) => {
+                    counters.errors.fetch_add(1, Ordering::Relaxed);
+                    Err(Error::Func(Arc::new(err)))
+                }
+                Err(payload) => {
+                    counters.panics.fetch_add(1, Ordering::Relaxed);
+                    Err(Error::Panicked(Panicked::new(&*payload)))
+                }
+            };
+
+            // callers that come after the end start a new stream
+            let mut streams = streams.lock();
+            if streams
+                .get::<K>(&key)
+                .is_some_and(|call| Arc::ptr_eq(call, &producer))
+            {
+                streams.remove::<K>(&key);
+            }
+            drop(streams);
+            counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+            producer.end(end);
+        }));
+
+        Subscription::new(call, self.propagate_panics)
+    }
+
+    // launch_deadline launches func for key and waits for its res until
+    // deadline. A caller whose call was dropped before it handed out a res
+    // looks the key up again.
+    fn launch_deadline<Q, F>(
+        &self,
+        op: &'static str,
+        key: &Q,
+        with_context: bool,
+        deadline: Instant,
+        func: F,
+    ) -> Result<(T, bool), Error<E>>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.shared.counters.calls.fetch_add(1, Ordering::Relaxed);
+        let span = trace::Span::new(op, || self.key_fmt.format(key));
+        let mut func = Some(func);
+        loop {
+            let flight = self.launch(key, span.clone(), with_context, &mut func);
+            if let Some(res) = self.wait_deadline(key, flight, deadline) {
+                return res;
+            }
+        }
+    }
+
+    // wait_deadline waits for the res of flight until deadline. It returns
+    // None if flight joined a call that was dropped without a res.
+    fn wait_deadline<Q>(
+        &self,
+        key: &Q,
+        flight: Flight<T, E>,
+        deadline: Instant,
+    ) -> Option<Result<(T, bool), Error<E>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let res = match flight.recv.recv_deadline(deadline) {
+            Ok(res) => {
+                flight.span.received();
+                self.check_received(&flight, res)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                match (flight.call, flight.waiter) {
+                    (Some(call), Some(id)) => self.shared.leave(&self.opts, key, &call, id),
+                    (Some(call), None) => call.lock().leader_leave(),
+                    _ => {}
+                }
+                Err(Error::Timeout)
+            }
+            // the async execution call was dropped
+            Err(RecvTimeoutError::Disconnected) if flight.waiter.is_some() => return None,
+            Err(RecvTimeoutError::Disconnected) => Err(Error::Dropped),
+        };
+        Some(res)
+    }
+
+    // launch joins the call for key or starts one that runs func on the
+    // group's executor. Only calls launched with_context can be cancelled.
+    // func is taken only if the caller does not join a call, so that it can
+    // try again if the call it joined is dropped.
+    fn launch<Q, F>(
+        &self,
+        key: &Q,
+        span: trace::Span,
+        with_context: bool,
+        func: &mut Option<F>,
+    ) -> Flight<T, E>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let share = self.shared.shard(key).lock();
+        self.launch_locked(share, key, span, with_context, func)
+    }
+
+    // launch_locked is launch for a caller that holds the shard of key
+    fn launch_locked<Q, F>(
+        &self,
+        mut share: MutexGuard<'_, HashMap<K, Entry<T, E>>>,
+        key: &Q,
+        span: trace::Span,
+        with_context: bool,
+        func: &mut Option<F>,
+    ) -> Flight<T, E>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        // a call that is over since it was looked up is looked up again
+        loop {
+            match Shared::lookup(&self.opts, &share, key) {
+                Some(Found::Done(done)) => {
+                    span.cached();
+                    let (s, r) = crossbeam::channel::bounded(1);
+                    s.send(done.get(&self.shared.counters)).unwrap();
+                    return Flight {
+                        call: None,
+                        waiter: None,
+                        recv: r,
+                        span,
+                    };
+                }
+                Some(Found::Call(call)) => {
+                    let mut c = call.lock();
+                    let Some((id, recv)) = c.join(None, false) else {
+                        continue;
+                    };
+                    span.follow(&c.trace, c.dup);
+                    drop(c);
+                    return Flight {
+                        call: Some(call.clone()),
+                        waiter: Some(id),
+                        recv,
+                        span,
+                    };
+                }
+                Some(Found::Full) => {
+                    drop(share);
+                    let (s, r) = crossbeam::channel::bounded(1);
+                    match self.opts.overflow {
+                        Overflow::Reject => s.send(Err(Error::TooManyWaiters)).unwrap(),
+                        Overflow::Run => {
+                            let func = func.take().expect("singleflight: func already ran");
+                            let shared = self.shared.clone();
+                            self.executor.execute(Box::new(move || {
+                                let res = match panic::catch_unwind(AssertUnwindSafe(|| {
+                                    func(CallContext::default())
+                                })) {
+                                    Ok(func_res) => shared.bypass(func_res),
+                                    Err(payload) => {
+                                        shared.counters.panics.fetch_add(1, Ordering::Relaxed);
+                                        Err(Error::Panicked(Panicked::new(&*payload)))
+                                    }
+                                };
+                                // the caller may have dropped the receiver
+                                let _ = s.send(res);
+                            }));
+                        }
+                    }
+                    return Flight {
+                        call: None,
+                        waiter: None,
+                        recv: r,
+                        span,
+                    };
+                }
+                None => break,
+            }
+        }
+
+        let func = func.take().expect("singleflight: func already ran");
+        let call = self.shared.start(&self.opts, &mut share, key);
+        let ctx = CallContext::default();
+        {
+            let mut c = call.lock();
+            span.lead(&mut c.trace);
+            if with_context {
+                c.ctx = Some(ctx.clone());
+            }
+        }
+        drop(share);
+
+        let (s, r): (ShareSender<T, E>, ShareReceiver<T, E>) = crossbeam::channel::bounded(1);
+        let run = self.execution(key.to_owned(), call.clone(), ctx, span.clone(), func);
+        self.executor
+            .execute(self.shared.task(key.to_owned(), call.clone(), s, run));
+
+        Flight {
+            call: Some(call),
+            waiter: None,
+            recv: r,
+            span,
+        }
+    }
+
+    // execution returns the execution call of call for key, which runs func
+    // and hands its res to the call's duplicate callers
+    fn execution<F>(
+        &self,
+        key: K,
+        call: CallRef<T, E>,
+        ctx: CallContext,
+        span: trace::Span,
+        func: F,
+    ) -> impl FnOnce() -> Result<(T, bool), Error<E>> + Send + 'static
+    where
+        K: Send + 'static,
+        F: FnOnce(CallContext) -> Result<T, E> + Send + 'static,
+        T: 'static,
+        E: Send + Sync + 'static,
+    {
+        let opts = self.opts.clone();
+        let shared = self.shared.clone();
+        let coordinated = self.coordinated.clone();
+        move || {
+            call.lock().leader = thread::current();
+            let func = || match &coordinated {
+                Some(coordinated) => coordinated.run(&key, || func(ctx)),
+                None => func(ctx),
+            };
+            match span.in_scope(|| panic::catch_unwind(AssertUnwindSafe(func))) {
+                Ok(func_res) => shared.finish::<K>(&opts, &key, &call, func_res),
+                Err(payload) => Err(Error::Panicked(
+                    shared.panicked::<K>(&key, &call, &*payload),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<K, T, E> Group<K, T, E>
+where
+    K: Hash + Eq + Clone + std::fmt::Debug,
+    T: Clone + Send,
+{
+    // trace_keys makes the spans of the group's callers carry their key,
+    // rendered with Debug. See trace_keys_with.
+    pub fn trace_keys(self) -> Self {
+        self.trace_keys_with(|key| format!("{key:?}"))
+    }
+}
+
+/// ArcGroup is a group whose values are wrapped in an [`Arc`] once, so every
+/// caller of a call gets a cheap handle on the same value instead of a clone.
+pub type ArcGroup<K, V, E = anyhow::Error> = Group<K, Arc<V>, E>;
+
+impl<K, V, E> Group<K, Arc<V>, E>
+where
+    K: Hash + Eq + Clone,
+    V: Send + Sync,
+{
+    // go_arc is like go for a func that returns a bare value, which is
+    // wrapped in an Arc and shared with every caller.
+    pub fn go_arc<Q, F>(&self, key: &Q, func: F) -> Result<(Arc<V>, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.go(key, || func().map(Arc::new))
+    }
+
+    // go_async_arc is like go_async for a func that returns a bare value.
+    pub async fn go_async_arc<Q, F, Fut>(
+        &self,
+        key: &Q,
+        func: F,
+    ) -> Result<(Arc<V>, bool), Error<E>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        self.go_async(key, || async { func().await.map(Arc::new) })
+            .await
+    }
+
+    // go_chan_arc is like go_chan for a func that returns a bare value.
+    pub fn go_chan_arc<Q, F>(&self, key: &Q, func: F) -> ShareReceiver<Arc<V>, E>
+    where
+        K: Borrow<Q> + Send + 'static,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> Result<V, E> + Send + 'static,
+        V: 'static,
+        E: Send + Sync + 'static,
+    {
+        self.go_chan(key, || func().map(Arc::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::future::Future;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use super::{Error, Group};
+
+    const RES: usize = 7;
+
+    // wait_dup blocks until dup duplicate callers have joined the call for key
+    fn wait_dup<E>(g: &Group<String, usize, E>, key: &str, dup: usize) {
+        while g
+            .shared
+            .shard(key)
+            .lock()
+            .get(key)
+            .and_then(|entry| match entry {
+                super::Entry::Calls(calls) => Some(calls.iter().map(|call| call.lock().dup).sum()),
+                super::Entry::Done(_) => None,
+            })
+            != Some(dup)
+        {
+            std::thread::yield_now();
+        }
+    }
+
+    // block_on drives fut to completion on the current thread, standing in
+    // for whatever executor the caller uses
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+                return res;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn test_go_simple() {
+        let g = Group::new();
+        let res = g.go("key", || Ok(RES));
+        // simple call's result should not be shared
+        assert_eq!(res.unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_chan_simple() {
+        let g = Group::new();
+        let ch = g.go_chan("key", || Ok(RES));
+        assert_eq!(ch.capacity().unwrap(), 1);
+        // simple call's result should not be shared
+        assert_eq!(ch.recv().unwrap().unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_structured_key() {
+        let g = Group::new();
+        let key = (String::from("tenant"), 42u64, 3u32);
+        let res = g.go(&key, || Ok(RES));
+        assert_eq!(res.unwrap(), (RES, false));
+        // the key is released once the call completes
+        assert!(g.shared.shard(&key).lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_multiple_threads() {
+        use std::time::Duration;
+
+        use crossbeam::thread;
+
+        fn expensive_fn() -> anyhow::Result<usize> {
+            std::thread::sleep(Duration::new(0, 500));
+            Ok(RES)
+        }
+
+        let g = Group::new();
+        thread::scope(|s| {
+            for _ in 0..10 {
+                s.spawn(|_| {
+                    let res = g.go("key", expensive_fn);
+                    // mutiple call's result may be shared by ohter duplicate calls
+                    assert_eq!(res.unwrap().0, RES);
+                });
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_chan_mutiple_threads() {
+        use std::time::Duration;
+
+        use crossbeam::thread;
+
+        fn expensive_fn() -> anyhow::Result<usize> {
+            std::thread::sleep(Duration::new(0, 500));
+            Ok(RES)
+        }
+
+        let g = Group::new();
+        thread::scope(|s| {
+            for _ in 0..10 {
                 s.spawn(|_| {
                     let ch = g.go_chan("key", expensive_fn);
                     assert_eq!(ch.capacity().unwrap(), 1);
@@ -239,4 +2169,1483 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn test_go_async_simple() {
+        let g = Group::new();
+        let res = block_on(g.go_async("key", || async { Ok(RES) }));
+        // simple call's result should not be shared
+        assert_eq!(res.unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_async_is_send() {
+        fn assert_send<F: Future + Send>(_: &F) {}
+
+        let g: Group<String, usize> = Group::new();
+        let fut = g.go_async("key", || async { Ok(RES) });
+        assert_send(&fut);
+    }
+
+    #[test]
+    fn test_go_async_multiple_threads() {
+        use std::time::Duration;
+
+        use crossbeam::thread;
+
+        async fn expensive_fn() -> anyhow::Result<usize> {
+            std::thread::sleep(Duration::from_millis(10));
+            Ok(RES)
+        }
+
+        let g = Group::new();
+        thread::scope(|s| {
+            for _ in 0..10 {
+                s.spawn(|_| {
+                    let res = block_on(g.go_async("key", expensive_fn));
+                    // mutiple call's result may be shared by ohter duplicate calls
+                    assert_eq!(res.unwrap().0, RES);
+                });
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_async_dropped_leader() {
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut leader = Box::pin(g.go_async("key", std::future::pending));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+
+        let mut dup = Box::pin(g.go_async("key", || async { Ok(RES) }));
+        assert!(dup.as_mut().poll(&mut cx).is_pending());
+
+        // the duplicate caller takes over once the original is dropped
+        drop(leader);
+        assert_eq!(block_on(dup).unwrap(), (RES, false));
+        assert!(g.shared.shard("key").lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_chan_dropped_leader() {
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut leader = Box::pin(g.go_async("key", std::future::pending));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        let dup = g.go_chan("key", || Ok(RES));
+        wait_dup(&g, "key", 1);
+
+        // the duplicate caller's func runs once the original is dropped
+        drop(leader);
+        assert_eq!(dup.recv().unwrap().unwrap(), (RES, false));
+        assert!(g.shared.shard("key").lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_deadline_dropped_leader() {
+        use std::time::Duration;
+
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut leader = Box::pin(g.go_async("key", std::future::pending));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        crossbeam::thread::scope(|s| {
+            let dup = s.spawn(|_| g.go_timeout("key", Duration::from_secs(60), || Ok(RES)));
+            wait_dup(&g, "key", 1);
+
+            // the duplicate caller starts a call of its own
+            drop(leader);
+            assert_eq!(dup.join().unwrap().unwrap(), (RES, false));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_stale_dropped_leader() {
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut leader = Box::pin(g.go_async("key", std::future::pending));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        crossbeam::thread::scope(|s| {
+            let dup = s.spawn(|_| g.go_stale("key", || Ok(RES)));
+            wait_dup(&g, "key", 1);
+
+            // the duplicate caller starts a call of its own
+            drop(leader);
+            let served = dup.join().unwrap().unwrap();
+            assert_eq!((served.val, served.shared), (RES, false));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_panic() {
+        use crossbeam::thread;
+
+        let g = Group::new();
+        thread::scope(|s| {
+            let leader = s.spawn(|_| {
+                g.go("key", || -> anyhow::Result<usize> {
+                    wait_dup(&g, "key", 1);
+                    panic!("boom")
+                })
+            });
+            let dup = s.spawn(|_| {
+                while g.shared.shard("key").lock().is_empty() {
+                    std::thread::yield_now();
+                }
+                g.go("key", || Ok(RES))
+            });
+
+            // the execution call re-raises its own panic
+            assert!(leader.join().is_err());
+            match dup.join().unwrap() {
+                Err(Error::Panicked(panicked)) => assert_eq!(panicked.message(), "boom"),
+                res => panic!("unexpected result {:?}", res),
+            }
+        })
+        .unwrap();
+
+        // the key is free for the next caller
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_propagate_panics() {
+        use crossbeam::thread;
+
+        let g = Group::new().propagate_panics(true);
+        thread::scope(|s| {
+            let leader = s.spawn(|_| {
+                g.go("key", || -> anyhow::Result<usize> {
+                    wait_dup(&g, "key", 1);
+                    panic!("boom")
+                })
+            });
+            let dup = s.spawn(|_| {
+                while g.shared.shard("key").lock().is_empty() {
+                    std::thread::yield_now();
+                }
+                g.go("key", || Ok(RES))
+            });
+
+            assert!(leader.join().is_err());
+            let payload = dup.join().unwrap_err();
+            let panicked = payload.downcast_ref::<super::Panicked>().unwrap();
+            assert_eq!(panicked.message(), "boom");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_chan_panic() {
+        let g: Group<String, usize> = Group::new();
+        let ch = g.go_chan("key", || panic!("boom"));
+        let err = ch.recv().unwrap().unwrap_err();
+        assert!(matches!(err, Error::Panicked(_)));
+        assert!(g.shared.shard("key").lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_timeout_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::time::Duration;
+
+        let g: Group<String, usize> = Group::new();
+        let timeout = Duration::from_secs(60);
+
+        // the caller re-raises the panic of its func run on the executor
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            g.go_timeout("key", timeout, || panic!("boom"))
+        }))
+        .unwrap_err();
+        let panicked = payload.downcast_ref::<super::Panicked>().unwrap();
+        assert_eq!(panicked.message(), "boom");
+        assert!(g.shared.shard("key").lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_dropped_task() {
+        use std::time::Duration;
+
+        use super::Task;
+
+        let g = Group::new().executor(|task: Task| drop(task));
+        let timeout = Duration::from_secs(60);
+
+        // callers whose func the executor drops get an error and leave no
+        // call behind
+        let res = g.go_timeout("key", timeout, || Ok(RES));
+        assert!(matches!(res, Err(Error::Dropped)));
+        assert!(matches!(g.go_stale("key", || Ok(RES)), Err(Error::Dropped)));
+        assert!(g.go_chan("key", || Ok(RES)).recv().is_err());
+        assert!(g.shared.shard("key").lock().is_empty());
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_async_panic() {
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut polled = false;
+        let mut leader = Box::pin(g.go_async("key", || {
+            std::future::poll_fn(move |_| -> Poll<anyhow::Result<usize>> {
+                if polled {
+                    panic!("boom");
+                }
+                polled = true;
+                Poll::Pending
+            })
+        }));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+
+        let mut dup = Box::pin(g.go_async("key", || async { Ok(RES) }));
+        assert!(dup.as_mut().poll(&mut cx).is_pending());
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| leader.as_mut().poll(&mut cx)));
+        assert!(res.is_err());
+        let err = block_on(dup).unwrap_err();
+        assert!(matches!(err, Error::Panicked(_)));
+        assert!(g.shared.shard("key").lock().is_empty());
+    }
+
+    #[test]
+    fn test_go_anyhow_error() {
+        fn load(g: &Group<String, usize>) -> anyhow::Result<usize> {
+            let (v, _) = g
+                .go("key", || {
+                    Err(anyhow::anyhow!("backend down").context("load failed"))
+                })
+                .map_err(Error::into_anyhow)?;
+            Ok(v)
+        }
+
+        let g = Group::new();
+        let err = load(&g).unwrap_err();
+        assert_eq!(err.to_string(), "load failed");
+        assert_eq!(err.root_cause().to_string(), "backend down");
+
+        // the chain survives an error that is still shared with the group
+        let g = Group::new().error_ttl(std::time::Duration::from_secs(60));
+        for _ in 0..2 {
+            let err = load(&g).unwrap_err();
+            assert_eq!(err.to_string(), "load failed");
+            assert_eq!(err.root_cause().to_string(), "backend down");
+        }
+    }
+
+    #[test]
+    fn test_go_typed_error() {
+        use std::error::Error as _;
+        use std::{fmt, io};
+
+        use crossbeam::thread;
+
+        #[derive(Debug)]
+        struct LoadError(io::Error);
+
+        impl fmt::Display for LoadError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "load failed")
+            }
+        }
+
+        impl std::error::Error for LoadError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let g: Group<String, usize, LoadError> = Group::default();
+        thread::scope(|s| {
+            let leader = s.spawn(|_| {
+                g.go("key", || {
+                    wait_dup(&g, "key", 1);
+                    Err(LoadError(io::Error::other("backend down")))
+                })
+            });
+            let dup = s.spawn(|_| {
+                while g.shared.shard("key").lock().is_empty() {
+                    std::thread::yield_now();
+                }
+                g.go("key", || Ok(RES))
+            });
+
+            let (leader, dup) = match (leader.join().unwrap(), dup.join().unwrap()) {
+                (Err(leader), Err(dup)) => (leader, dup),
+                res => panic!("unexpected result {:?}", res),
+            };
+            // every caller gets back the very same error
+            let leader_err = leader.func_error().unwrap();
+            let dup_err = dup.func_error().unwrap();
+            assert!(std::ptr::eq(leader_err, dup_err));
+            assert_eq!(dup_err.0.kind(), io::ErrorKind::Other);
+
+            assert_eq!(dup.to_string(), "load failed");
+            assert_eq!(dup.source().unwrap().to_string(), "backend down");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_chan_non_blocking() {
+        use crossbeam::channel::{bounded, select};
+        use std::time::Duration;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let ch = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+        let dup = g.go_chan("key", || Ok(RES + 1));
+        assert_eq!(dup.capacity().unwrap(), 1);
+
+        // nothing has been received while func is still running
+        select! {
+            recv(ch) -> _ => panic!("go_chan should not block on func"),
+            default(Duration::from_millis(10)) => {}
+        }
+
+        release.send(()).unwrap();
+        assert_eq!(ch.recv().unwrap().unwrap(), (RES, true));
+        assert_eq!(dup.recv().unwrap().unwrap(), (RES, true));
+    }
+
+    #[test]
+    fn test_go_chan_executor() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let executed = Arc::new(AtomicUsize::new(0));
+        let counter = executed.clone();
+        let g = Group::new().executor(move |task: super::Task| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            // run inline instead of on a new thread
+            task()
+        });
+
+        let ch = g.go_chan("key", || Ok(RES));
+        assert_eq!(ch.recv().unwrap().unwrap(), (RES, false));
+        assert_eq!(executed.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_forget() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+        let dup = g.go_chan("key", || Ok(0));
+
+        g.forget("key");
+        // new callers start a fresh execution
+        assert_eq!(g.go("key", || Ok(RES + 1)).unwrap(), (RES + 1, false));
+
+        let (release_second, released_second) = bounded::<()>(0);
+        let second = g.go_chan("key", move || {
+            released_second.recv().unwrap();
+            Ok(RES + 2)
+        });
+
+        // the forgotten call still serves the callers that joined it and
+        // leaves the newer call in place
+        release.send(()).unwrap();
+        assert_eq!(first.recv().unwrap().unwrap(), (RES, true));
+        assert_eq!(dup.recv().unwrap().unwrap(), (RES, true));
+        assert!(g.shared.shard("key").lock().contains_key("key"));
+
+        release_second.send(()).unwrap();
+        assert_eq!(second.recv().unwrap().unwrap(), (RES + 2, false));
+    }
+
+    #[test]
+    fn test_go_fn_once() {
+        // a row type without a sensible default
+        #[derive(Debug, Clone, PartialEq)]
+        struct Row {
+            id: u64,
+            name: String,
+        }
+
+        let g = Group::new();
+        let name = String::from("owned");
+        let res = g.go(&1u64, move || Ok(Row { id: 1, name }));
+        let row = Row {
+            id: 1,
+            name: String::from("owned"),
+        };
+        assert_eq!(res.unwrap(), (row, false));
+    }
+
+    #[test]
+    fn test_go_timeout() {
+        use crossbeam::channel::bounded;
+        use std::time::Duration;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+
+        let res = g.go_timeout("key", Duration::from_millis(10), || Ok(0));
+        assert!(matches!(res, Err(Error::Timeout)));
+        // the duplicate caller that gave up no longer counts
+        wait_dup(&g, "key", 0);
+
+        release.send(()).unwrap();
+        assert_eq!(first.recv().unwrap().unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_deadline_leader() {
+        use crossbeam::channel::bounded;
+        use std::time::{Duration, Instant};
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let deadline = Instant::now() + Duration::from_millis(10);
+        let res = g.go_deadline("key", deadline, move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+        assert!(matches!(res, Err(Error::Timeout)));
+
+        // the execution call keeps running for later callers
+        let dup = g.go_chan("key", || Ok(0));
+        release.send(()).unwrap();
+        assert_eq!(dup.recv().unwrap().unwrap(), (RES, true));
+    }
+
+    #[test]
+    fn test_go_cancelled() {
+        use crossbeam::channel::bounded;
+        use crossbeam::thread;
+        use std::time::Duration;
+
+        let g = Group::new();
+        let (cancelled, observed) = bounded(1);
+        thread::scope(|s| {
+            s.spawn(|_| {
+                let res = g.go_timeout_with_context("key", Duration::from_millis(50), move |ctx| {
+                    while !ctx.is_cancelled() {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                    cancelled.send(()).unwrap();
+                    Ok(RES)
+                });
+                assert!(matches!(res, Err(Error::Timeout)));
+                // the duplicate caller still waits
+                assert!(observed.try_recv().is_err());
+            });
+            s.spawn(|_| {
+                while g.shared.shard("key").lock().is_empty() {
+                    std::thread::yield_now();
+                }
+                let res = g.go_timeout("key", Duration::from_millis(200), || Ok(0));
+                assert!(matches!(res, Err(Error::Timeout)));
+            });
+        })
+        .unwrap();
+
+        // the call is cancelled once both callers gave up
+        observed.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_join_cancelled() {
+        use super::{Call, CallContext};
+
+        // a call cancelled after it was looked up is not joined
+        let mut call = Call::<usize, anyhow::Error>::new(Arc::default());
+        let ctx = CallContext::default();
+        call.ctx = Some(ctx.clone());
+        assert!(call.join(None, false).is_some());
+        ctx.cancel();
+        assert!(call.join(None, false).is_none());
+        assert_eq!(call.dup, 1);
+    }
+
+    #[test]
+    fn test_go_cancelled_uncached() {
+        use std::time::Duration;
+
+        let g = Group::new()
+            .error_ttl(Duration::from_secs(60))
+            .error_backoff(Duration::from_secs(60), Duration::from_secs(60));
+        let res = g.go_timeout_with_context("key", Duration::from_millis(10), |ctx| {
+            while !ctx.is_cancelled() {
+                std::thread::yield_now();
+            }
+            Err(anyhow::anyhow!("cancelled"))
+        });
+        assert!(matches!(res, Err(Error::Timeout)));
+        while g.stats().in_flight > 0 {
+            std::thread::yield_now();
+        }
+
+        // the bail-out error of the cancelled call is not served
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_cancelled_restart() {
+        use std::time::Duration;
+
+        let g = Group::new();
+        let res = g.go_timeout_with_context("key", Duration::from_millis(10), |ctx| {
+            while !ctx.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok(0)
+        });
+        assert!(matches!(res, Err(Error::Timeout)));
+
+        // new callers do not join the cancelled call
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_async_dropped_dup() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+
+        let mut dup = Box::pin(g.go_async("key", || async { Ok(0) }));
+        assert!(dup.as_mut().poll(&mut cx).is_pending());
+        wait_dup(&g, "key", 1);
+
+        // a dropped async caller no longer counts as a duplicate
+        drop(dup);
+        wait_dup(&g, "key", 0);
+        release.send(()).unwrap();
+        assert_eq!(first.recv().unwrap().unwrap(), (RES, false));
+    }
+
+    #[test]
+    fn test_go_ttl() {
+        use std::time::Duration;
+
+        let g = Group::new().ttl(Duration::from_millis(50));
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+
+        // completed values are served until they expire
+        assert_eq!(g.go("key", || Ok(0)).unwrap(), (RES, true));
+        let ch = g.go_chan("key", || Ok(0));
+        assert_eq!(ch.recv().unwrap().unwrap(), (RES, true));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(g.go("key", || Ok(RES + 1)).unwrap(), (RES + 1, false));
+
+        // forget drops a kept value early
+        g.forget("key");
+        assert_eq!(g.go("key", || Ok(RES + 2)).unwrap(), (RES + 2, false));
+    }
+
+    #[test]
+    fn test_go_ttl_cold_keys() {
+        use std::time::Duration;
+
+        let g = Group::new().ttl(Duration::from_millis(1));
+        let keys = |range: std::ops::Range<usize>| range.map(|i| format!("key{i}"));
+        for key in keys(0..1000) {
+            g.go(&key, || Ok(RES)).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(10));
+
+        // expired values of keys that are not called again go away as
+        // other keys come in
+        for key in keys(1000..4000) {
+            g.go(&key, || Ok(RES)).unwrap();
+        }
+        for key in keys(0..1000) {
+            assert!(g.shared.shard(&key).lock().get(&key).is_none());
+        }
+    }
+
+    #[test]
+    fn test_go_error_ttl() {
+        use std::time::Duration;
+
+        let g = Group::new().ttl(Duration::from_secs(60));
+        assert!(g.go("key", || Err(anyhow::anyhow!("down"))).is_err());
+        // errors are not kept unless error_ttl is set
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+
+        let g = Group::new().error_ttl(Duration::from_secs(60));
+        assert!(g.go("key", || Err(anyhow::anyhow!("down"))).is_err());
+        let err = g.go("key", || Ok(RES)).unwrap_err();
+        assert_eq!(err.to_string(), "down");
+        // values are not kept unless ttl is set
+        g.forget("key");
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+        assert_eq!(g.go("key", || Ok(RES + 1)).unwrap(), (RES + 1, false));
+    }
+
+    #[test]
+    fn test_go_error_backoff() {
+        use std::time::Duration;
+
+        let g = Group::new().error_backoff(Duration::from_millis(50), Duration::from_secs(1));
+        let fail = || Err(anyhow::anyhow!("down"));
+        assert!(g.go("key", fail).is_err());
+        assert!(g.go("key", || Ok(RES)).is_err());
+        assert_eq!(g.stats().executions, 1);
+
+        // the window doubles on the next failure
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(g.go("key", fail).is_err());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(g.go("key", || Ok(RES)).is_err());
+        assert_eq!(g.stats().executions, 2);
+
+        // and resets on success
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+        assert!(g.go("key", fail).is_err());
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(g.go("key", || Ok(RES)).unwrap(), (RES, false));
+        assert_eq!(g.stats().executions, 5);
+    }
+
+    #[test]
+    fn test_go_takeover() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new().takeover(1, |err: &anyhow::Error| err.to_string() == "busy");
+        let busy = || Err(anyhow::anyhow!("busy"));
+
+        for (key, dup_res) in [("ok", Ok(RES)), ("fail", Err("busy"))] {
+            let (release, released) = bounded::<()>(0);
+            crossbeam::thread::scope(|s| {
+                let leader = s.spawn(|_| {
+                    g.go(key, || {
+                        released.recv().unwrap();
+                        busy()
+                    })
+                });
+                wait_dup(&g, key, 0);
+                let dups: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|_| {
+                            g.go(key, || match dup_res {
+                                Ok(res) => Ok(res),
+                                Err(_) => busy(),
+                            })
+                        })
+                    })
+                    .collect();
+                wait_dup(&g, key, 2);
+                release.send(()).unwrap();
+
+                assert_eq!(leader.join().unwrap().unwrap_err().to_string(), "busy");
+                for dup in dups {
+                    // one dup ran its func, the other one got its res
+                    match (dup.join().unwrap(), dup_res) {
+                        (Ok(res), Ok(val)) => assert_eq!(res, (val, true)),
+                        (Err(err), Err(msg)) => assert_eq!(err.to_string(), msg),
+                        (res, _) => panic!("unexpected res {:?}", res.map(|(val, _)| val)),
+                    }
+                }
+            })
+            .unwrap();
+        }
+        // takeovers are bounded, so the second dup of fail got the error
+        assert_eq!(g.stats().executions, 4);
+    }
+
+    #[test]
+    fn test_go_takeover_dropped() {
+        use std::time::Duration;
+
+        use crossbeam::channel::bounded;
+
+        let g = Group::new()
+            .error_ttl(Duration::from_secs(60))
+            .takeover(1, |err: &anyhow::Error| err.to_string() == "busy");
+        let mut cx = Context::from_waker(Waker::noop());
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Err(anyhow::anyhow!("busy"))
+        });
+
+        let mut dup = Box::pin(g.go_async("key", || async { Ok(RES) }));
+        assert!(dup.as_mut().poll(&mut cx).is_pending());
+        wait_dup(&g, "key", 1);
+        release.send(()).unwrap();
+        assert_eq!(first.recv().unwrap().unwrap_err().to_string(), "busy");
+
+        // a promoted caller dropped before running its func settles the
+        // call with the error it was promoted for
+        drop(dup);
+        assert!(g.inflight().is_empty());
+        let err = g.go("key", || Ok(RES)).unwrap_err();
+        assert_eq!(err.to_string(), "busy");
+        let stats = g.stats();
+        assert!(stats.suppressed + stats.executions <= stats.calls);
+    }
+
+    #[test]
+    fn test_go_stale() {
+        use std::time::Duration;
+
+        use crossbeam::channel::bounded;
+
+        use super::Served;
+
+        let g = Group::new()
+            .ttl(Duration::from_millis(50))
+            .stale_while_revalidate(Duration::from_secs(60));
+        let served = |val, shared, stale| Served { val, shared, stale };
+        assert_eq!(
+            g.go_stale("key", || Ok(RES)).unwrap(),
+            served(RES, false, false)
+        );
+        assert_eq!(
+            g.go_stale("key", || Ok(0)).unwrap(),
+            served(RES, true, false)
+        );
+
+        // expired values are served while a single call refreshes them
+        std::thread::sleep(Duration::from_millis(60));
+        let (release, released) = bounded::<()>(0);
+        let refresh = move || {
+            released.recv().unwrap();
+            Ok(RES + 1)
+        };
+        assert_eq!(g.go_stale("key", refresh).unwrap(), served(RES, true, true));
+        assert_eq!(
+            g.go_stale("key", || Ok(0)).unwrap(),
+            served(RES, true, true)
+        );
+        assert_eq!(g.stats().executions, 2);
+
+        // callers of go wait for the refresh
+        release.send(()).unwrap();
+        assert_eq!(g.go("key", || Ok(0)).unwrap(), (RES + 1, true));
+        assert_eq!(
+            g.go_stale("key", || Ok(0)).unwrap(),
+            served(RES + 1, true, false)
+        );
+    }
+
+    #[test]
+    fn test_go_max_inflight() {
+        use crossbeam::channel::bounded;
+
+        use super::JoinPolicy;
+
+        for (join, dups) in [
+            (JoinPolicy::Earliest, [2, 0]),
+            (JoinPolicy::LeastLoaded, [1, 1]),
+        ] {
+            let g = Group::new().max_inflight_per_key(2, join);
+            let (release, released) = bounded::<()>(0);
+            crossbeam::thread::scope(|s| {
+                let leaders: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|_| {
+                            g.go("key", || {
+                                released.recv().unwrap();
+                                Ok(RES)
+                            })
+                        })
+                    })
+                    .collect();
+                while g.stats().in_flight < 2 {
+                    std::thread::yield_now();
+                }
+                let followers: Vec<_> =
+                    (0..2).map(|_| s.spawn(|_| g.go("key", || Ok(0)))).collect();
+                wait_dup(&g, "key", 2);
+
+                match g.shared.shard("key").lock().get("key") {
+                    Some(super::Entry::Calls(calls)) => {
+                        let got: Vec<_> = calls.iter().map(|call| call.lock().dup).collect();
+                        assert_eq!(got, dups);
+                    }
+                    _ => panic!("no calls in flight"),
+                }
+
+                release.send(()).unwrap();
+                release.send(()).unwrap();
+                for handle in leaders.into_iter().chain(followers) {
+                    assert_eq!(handle.join().unwrap().unwrap().0, RES);
+                }
+            })
+            .unwrap();
+            assert_eq!(g.stats().executions, 2);
+        }
+    }
+
+    #[test]
+    fn test_batch_group() {
+        use std::time::Duration;
+
+        use parking_lot::Mutex;
+
+        use super::BatchGroup;
+
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batch_fn = || {
+            let batches = batches.clone();
+            move |keys: &[String]| -> Vec<anyhow::Result<usize>> {
+                batches.lock().push(keys.to_vec());
+                keys.iter().map(|key| Ok(key.len())).collect()
+            }
+        };
+
+        // a full batch is loaded right away
+        let bg = BatchGroup::new(batch_fn())
+            .window(Duration::from_secs(60))
+            .max_batch(2);
+        crossbeam::thread::scope(|s| {
+            let first = s.spawn(|_| bg.load("a"));
+            while bg.stats().in_flight == 0 {
+                std::thread::yield_now();
+            }
+            // a key being loaded is not added to the batch again
+            let dup = s.spawn(|_| bg.load("a"));
+            while bg.stats().suppressed == 0 {
+                std::thread::yield_now();
+            }
+            let full = s.spawn(|_| bg.load("bb"));
+
+            assert_eq!(first.join().unwrap().unwrap(), (1, true));
+            assert_eq!(dup.join().unwrap().unwrap(), (1, true));
+            assert_eq!(full.join().unwrap().unwrap(), (2, false));
+        })
+        .unwrap();
+        assert_eq!(
+            *batches.lock(),
+            vec![vec!["a".to_string(), "bb".to_string()]]
+        );
+
+        // otherwise once its window closes
+        let bg = BatchGroup::new(batch_fn()).window(Duration::from_millis(10));
+        assert_eq!(bg.load("ccc").unwrap(), (3, false));
+        assert_eq!(batches.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_go_stream() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let mut first = g.go_stream("key", move || {
+            (0..3).map(move |i| {
+                if i == 2 {
+                    released.recv().unwrap();
+                }
+                Ok(i)
+            })
+        });
+        assert_eq!(first.next().unwrap().unwrap(), 0);
+        assert_eq!(first.next().unwrap().unwrap(), 1);
+
+        // a late subscriber gets the items it missed, then the live ones
+        let late = g.go_stream("key", || -> Vec<anyhow::Result<i32>> {
+            panic!("unexpected call")
+        });
+        release.send(()).unwrap();
+        let late: Vec<_> = late.map(Result::unwrap).collect();
+        assert_eq!(late, vec![0, 1, 2]);
+        assert_eq!(first.next().unwrap().unwrap(), 2);
+        assert!(first.next().is_none());
+
+        let stats = g.stats();
+        assert_eq!((stats.executions, stats.suppressed), (1, 1));
+
+        // an error ends the stream of every subscriber
+        let mut failed = g.go_stream("err", || vec![Ok(1), Err(anyhow::anyhow!("boom"))]);
+        assert_eq!(failed.next().unwrap().unwrap(), 1);
+        let err = failed.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+        assert!(failed.next().is_none());
+
+        // forget makes later callers start a new stream
+        let (release, released) = bounded::<()>(0);
+        let forgotten = g.go_stream("key", move || {
+            released.recv().unwrap();
+            vec![Ok(0)]
+        });
+        g.forget("key");
+        let fresh: Vec<_> = g.go_stream("key", || vec![Ok(1)]).collect();
+        assert_eq!(
+            fresh.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![1]
+        );
+        release.send(()).unwrap();
+        let forgotten: Vec<_> = forgotten.map(Result::unwrap).collect();
+        assert_eq!(forgotten, vec![0]);
+    }
+
+    #[test]
+    fn test_go_stream_propagate_panics() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let g = Group::new().propagate_panics(true);
+        let mut sub = g.go_stream("key", || -> Vec<anyhow::Result<i32>> { panic!("boom") });
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| sub.next())).unwrap_err();
+        let panicked = payload.downcast_ref::<super::Panicked>().unwrap();
+        assert_eq!(panicked.message(), "boom");
+
+        let g = Group::new();
+        let mut sub = g.go_stream("key", || -> Vec<anyhow::Result<i32>> { panic!("boom") });
+        assert!(matches!(sub.next(), Some(Err(super::Error::Panicked(_)))));
+    }
+
+    #[test]
+    fn test_go_max_waiters() {
+        use crossbeam::channel::bounded;
+
+        use super::Overflow;
+
+        for overflow in [Overflow::Reject, Overflow::Run] {
+            let g = Group::new().max_waiters_per_key(1, overflow);
+            let (release, released) = bounded::<()>(0);
+            crossbeam::thread::scope(|s| {
+                let leader = s.spawn(|_| {
+                    g.go("key", || {
+                        released.recv().unwrap();
+                        Ok(RES)
+                    })
+                });
+                while g.stats().in_flight == 0 {
+                    std::thread::yield_now();
+                }
+                let dup = s.spawn(|_| g.go("key", || Ok(RES)));
+                wait_dup(&g, "key", 1);
+
+                // the call is full, so the caller does not wait for it
+                let res = g.go("key", || Ok(RES + 1));
+                match overflow {
+                    Overflow::Reject => assert!(matches!(res, Err(Error::TooManyWaiters))),
+                    Overflow::Run => assert_eq!(res.unwrap(), (RES + 1, false)),
+                }
+                let res = g.go_chan("key", || Ok(RES + 1)).recv().unwrap();
+                match overflow {
+                    Overflow::Reject => assert!(matches!(res, Err(Error::TooManyWaiters))),
+                    Overflow::Run => assert_eq!(res.unwrap(), (RES + 1, false)),
+                }
+
+                release.send(()).unwrap();
+                assert_eq!(leader.join().unwrap().unwrap(), (RES, true));
+                assert_eq!(dup.join().unwrap().unwrap(), (RES, true));
+            })
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_go_max_waiters_inflight() {
+        use crossbeam::channel::bounded;
+
+        use super::{JoinPolicy, Overflow};
+
+        let g = Group::new()
+            .max_inflight_per_key(2, JoinPolicy::Earliest)
+            .max_waiters_per_key(1, Overflow::Reject);
+        let (release, released) = bounded::<()>(0);
+        crossbeam::thread::scope(|s| {
+            let leaders: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|_| {
+                        g.go("key", || {
+                            released.recv().unwrap();
+                            Ok(RES)
+                        })
+                    })
+                })
+                .collect();
+            while g.stats().in_flight < 2 {
+                std::thread::yield_now();
+            }
+
+            // a full call is passed over for one that has room
+            let followers: Vec<_> = (0..2)
+                .map(|i| {
+                    let follower = s.spawn(|_| g.go("key", || Ok(0)));
+                    wait_dup(&g, "key", i + 1);
+                    follower
+                })
+                .collect();
+            assert!(matches!(g.go("key", || Ok(0)), Err(Error::TooManyWaiters)));
+
+            release.send(()).unwrap();
+            release.send(()).unwrap();
+            for handle in leaders.into_iter().chain(followers) {
+                assert_eq!(handle.join().unwrap().unwrap().0, RES);
+            }
+        })
+        .unwrap();
+        assert_eq!(g.stats().executions, 2);
+    }
+
+    #[test]
+    fn test_inflight() {
+        use std::time::Instant;
+
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        crossbeam::thread::scope(|s| {
+            let leader = s
+                .builder()
+                .name("leader".to_string())
+                .spawn(|_| {
+                    g.go("key", || {
+                        released.recv().unwrap();
+                        Ok(RES)
+                    })
+                })
+                .unwrap();
+            while g.stats().in_flight == 0 {
+                std::thread::yield_now();
+            }
+            let dup = s.spawn(|_| g.go("key", || Ok(RES)));
+            wait_dup(&g, "key", 1);
+
+            let inflight = g.inflight();
+            assert_eq!(inflight.len(), 1);
+            assert_eq!(inflight[0].key, "key");
+            assert_eq!(inflight[0].dup, 1);
+            assert_eq!(inflight[0].leader.as_deref(), Some("leader"));
+            assert!(inflight[0].started <= Instant::now());
+
+            release.send(()).unwrap();
+            leader.join().unwrap().unwrap();
+            dup.join().unwrap().unwrap();
+        })
+        .unwrap();
+        assert!(g.inflight().is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan("key", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+        let dup = g.go_chan("key", || Ok(0));
+
+        let stats = g.stats();
+        assert_eq!((stats.calls, stats.executions, stats.suppressed), (2, 1, 1));
+        assert_eq!((stats.in_flight, stats.waiters), (1, 1));
+
+        release.send(()).unwrap();
+        first.recv().unwrap().unwrap();
+        dup.recv().unwrap().unwrap();
+        assert!(g.go("key", || Err(anyhow::anyhow!("down"))).is_err());
+        let res = panic::catch_unwind(AssertUnwindSafe(|| g.go("key", || panic!("boom"))));
+        assert!(res.is_err());
+
+        assert_eq!(
+            g.stats(),
+            super::Stats {
+                calls: 4,
+                executions: 3,
+                suppressed: 1,
+                in_flight: 0,
+                waiters: 0,
+                errors: 1,
+                panics: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_go_shards() {
+        use crossbeam::thread;
+
+        for shards in [0, 1, 7, 64] {
+            let g = Group::new().shards(shards);
+            assert_eq!(g.shared.shards.len(), shards.max(1));
+            thread::scope(|s| {
+                for i in 0..32 {
+                    let g = &g;
+                    s.spawn(move |_| {
+                        let key = format!("key-{}", i % 8);
+                        assert_eq!(g.go(&key, || Ok(i % 8)).unwrap().0, i % 8);
+                    });
+                }
+            })
+            .unwrap();
+            assert!(g.shared.shards.iter().all(|shard| shard.lock().is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_arc_group() {
+        use crossbeam::channel::bounded;
+
+        use super::ArcGroup;
+
+        // a payload that cannot be cloned
+        #[derive(Debug, PartialEq)]
+        struct Payload(Vec<u8>);
+
+        let g: ArcGroup<String, Payload> = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let first = g.go_chan_arc("key", move || {
+            released.recv().unwrap();
+            Ok(Payload(vec![7; 1024]))
+        });
+        let dup = g.go_chan_arc("key", || Ok(Payload(Vec::new())));
+        release.send(()).unwrap();
+
+        let (first, shared) = first.recv().unwrap().unwrap();
+        assert!(shared);
+        let (dup, _) = dup.recv().unwrap().unwrap();
+        // every caller holds the very same value
+        assert!(Arc::ptr_eq(&first, &dup));
+        assert_eq!(first.0.len(), 1024);
+
+        let (res, _) = g.go_arc("key", || Ok(Payload(vec![1]))).unwrap();
+        assert_eq!(*res, Payload(vec![1]));
+        let (res, _) = block_on(g.go_async_arc("key", || async { Ok(Payload(vec![2])) })).unwrap();
+        assert_eq!(*res, Payload(vec![2]));
+    }
+
+    #[test]
+    fn test_go_many() {
+        use crossbeam::channel::bounded;
+        use std::time::Duration;
+
+        let g = Group::new().ttl(Duration::from_secs(60));
+        assert_eq!(g.go("cached", || Ok(1)).unwrap(), (1, false));
+
+        let (release, released) = bounded::<()>(0);
+        let in_flight = g.go_chan("in-flight", move || {
+            released.recv().unwrap();
+            Ok(2)
+        });
+
+        let keys = ["cached", "in-flight", "a", "b", "a"].map(String::from);
+        let res = g.go_many(keys, |keys| {
+            // only the keys nobody else handles are loaded, each one once
+            assert_eq!(keys, ["a", "b"]);
+            release.send(()).unwrap();
+            vec![Ok(3), Err(anyhow::anyhow!("missing"))]
+        });
+
+        assert_eq!(res.len(), 4);
+        assert_eq!(*res["cached"].as_ref().unwrap(), (1, true));
+        assert_eq!(*res["in-flight"].as_ref().unwrap(), (2, true));
+        assert_eq!(*res["a"].as_ref().unwrap(), (3, false));
+        assert_eq!(res["b"].as_ref().unwrap_err().to_string(), "missing");
+        assert_eq!(in_flight.recv().unwrap().unwrap(), (2, true));
+    }
+
+    #[test]
+    fn test_go_many_dropped_leader() {
+        let g = Group::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut leader = Box::pin(g.go_async("a", std::future::pending));
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        crossbeam::thread::scope(|s| {
+            let many = s.spawn(|_| {
+                g.go_many(["a".to_string()], |keys| {
+                    keys.iter().map(|key| Ok(key.len())).collect()
+                })
+            });
+            wait_dup(&g, "a", 1);
+
+            // the key of the dropped call is loaded by go_many itself
+            drop(leader);
+            let res = many.join().unwrap();
+            assert_eq!(*res["a"].as_ref().unwrap(), (1, false));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_go_many_panic() {
+        use crossbeam::channel::bounded;
+
+        let g = Group::new();
+        let (release, released) = bounded::<()>(0);
+        let in_flight = g.go_chan("a", move || {
+            released.recv().unwrap();
+            Ok(RES)
+        });
+
+        let keys = ["a", "b"].map(String::from);
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            g.go_many(keys, |_| -> Vec<anyhow::Result<usize>> { panic!("boom") })
+        }));
+        assert!(res.is_err());
+
+        // the call joined by go_many no longer counts it as a waiter
+        wait_dup(&g, "a", 0);
+        assert_eq!(g.stats().waiters, 0);
+        release.send(()).unwrap();
+        assert_eq!(in_flight.recv().unwrap().unwrap(), (RES, false));
+    }
+
+    // wait_flock_waiter waits until a process blocks on the lock file in
+    // dir, as listed in /proc/locks
+    #[cfg(target_os = "linux")]
+    fn wait_flock_waiter(dir: &std::path::Path) {
+        use std::os::unix::fs::MetadataExt;
+
+        let lock = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "lock"))
+            .unwrap();
+        let ino = format!(":{} ", std::fs::metadata(lock).unwrap().ino());
+        while !std::fs::read_to_string("/proc/locks")
+            .unwrap()
+            .lines()
+            .any(|line| line.contains("->") && line.contains(&ino))
+        {
+            std::thread::yield_now();
+        }
+    }
+
+    // blocked flock requests are only observable on linux
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_go_coordinator() {
+        use crossbeam::channel::bounded;
+
+        use super::FileCoordinator;
+
+        // groups sharing a coordinator stand in for processes on a host
+        let dir = std::env::temp_dir().join(format!("singleflight-{}", std::process::id()));
+        let g1 = Group::new().coordinator(FileCoordinator::new(&dir).unwrap());
+        let g2 = Group::new().coordinator(FileCoordinator::new(&dir).unwrap());
+        let (started, start) = bounded(0);
+        let (release, released) = bounded::<()>(0);
+
+        crossbeam::thread::scope(|s| {
+            let leader = s.spawn(|_| {
+                g1.go("key", || {
+                    started.send(()).unwrap();
+                    released.recv().unwrap();
+                    Ok(RES)
+                })
+            });
+            start.recv().unwrap();
+            let dup = s.spawn(|_| g2.go("key", || Ok(0)));
+            wait_flock_waiter(&dir);
+            release.send(()).unwrap();
+
+            assert_eq!(leader.join().unwrap().unwrap(), (RES, false));
+            assert_eq!(dup.join().unwrap().unwrap(), (RES, false));
+        })
+        .unwrap();
+
+        // a failed execution call is not shared
+        let _ = g1.go("fail", || Err(anyhow::anyhow!("down")));
+        assert_eq!(g2.go("fail", || Ok(RES)).unwrap(), (RES, false));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "tower")]
+    #[test]
+    fn test_singleflight_layer() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crossbeam::channel::{bounded, Receiver};
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        use super::SingleflightLayer;
+
+        // Backend answers a request with its length once released
+        #[derive(Clone)]
+        struct Backend {
+            calls: Arc<AtomicUsize>,
+            released: Receiver<()>,
+        }
+
+        impl Service<String> for Backend {
+            type Response = usize;
+            type Error = std::io::Error;
+            type Future =
+                std::pin::Pin<Box<dyn Future<Output = Result<usize, std::io::Error>> + Send>>;
+
+            fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, req: String) -> Self::Future {
+                self.calls.fetch_add(1, Ordering::Relaxed);
+                let released = self.released.clone();
+                Box::pin(async move {
+                    released.recv().unwrap();
+                    Ok(req.len())
+                })
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (release, released) = bounded::<()>(0);
+        let svc = SingleflightLayer::new(|req: &String| req.clone()).layer(Backend {
+            calls: calls.clone(),
+            released,
+        });
+
+        crossbeam::thread::scope(|s| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let mut svc = svc.clone();
+                    s.spawn(move |_| block_on(svc.call("key".to_string())))
+                })
+                .collect();
+            // the second request waits for the call of the first one
+            wait_dup(&svc.group::<String, usize, std::io::Error>(), "key", 1);
+            release.send(()).unwrap();
+
+            for handle in handles {
+                assert_eq!(handle.join().unwrap().unwrap(), 3);
+            }
+        })
+        .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_go_tracing() {
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::time::{Duration, Instant};
+
+        use crossbeam::channel::bounded;
+        use parking_lot::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Dispatch, Event, Metadata, Subscriber};
+
+        // Recorder keeps the fields of every span and the spans they
+        // follow from
+        #[derive(Default)]
+        struct Recorder {
+            next: AtomicU64,
+            spans: Mutex<HashMap<u64, HashMap<String, String>>>,
+            follows: Mutex<Vec<(u64, u64)>>,
+        }
+
+        struct Fields<'a>(&'a mut HashMap<String, String>);
+
+        impl Visit for Fields<'_> {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.insert(field.name().to_string(), value.to_string());
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{value:?}"));
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+                let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+                let mut fields = HashMap::new();
+                attrs.record(&mut Fields(&mut fields));
+                self.spans.lock().insert(id, fields);
+                Id::from_u64(id)
+            }
+
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                let mut spans = self.spans.lock();
+                values.record(&mut Fields(spans.get_mut(&span.into_u64()).unwrap()));
+            }
+
+            fn record_follows_from(&self, span: &Id, follows: &Id) {
+                self.follows
+                    .lock()
+                    .push((span.into_u64(), follows.into_u64()));
+            }
+
+            fn event(&self, _: &Event<'_>) {}
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let dispatch = Dispatch::from(recorder.clone());
+        let g = Group::new().trace_keys();
+        let (started, start) = bounded(0);
+        let (release, released) = bounded::<()>(0);
+
+        crossbeam::thread::scope(|s| {
+            s.spawn(|_| {
+                tracing::dispatcher::with_default(&dispatch, || {
+                    g.go("key", || {
+                        started.send(()).unwrap();
+                        released.recv().unwrap();
+                        Ok(RES)
+                    })
+                })
+            });
+            start.recv().unwrap();
+            s.spawn(|_| tracing::dispatcher::with_default(&dispatch, || g.go("key", || Ok(0))));
+            wait_dup(&g, "key", 1);
+            release.send(()).unwrap();
+        })
+        .unwrap();
+
+        let spans = recorder.spans.lock();
+        let role = |id: u64| spans[&id]["role"].as_str();
+        assert_eq!((role(1), role(2)), ("leader", "follower"));
+        assert_eq!(spans[&1]["key"], "\"key\"");
+        assert_eq!(spans[&1]["dup"], "1");
+        assert!(spans[&2].contains_key("wait_us"));
+        assert_eq!(*recorder.follows.lock(), vec![(2, 1)]);
+        drop(spans);
+
+        // spans are named after the method that was called
+        tracing::dispatcher::with_default(&dispatch, || {
+            let timeout = Duration::from_secs(60);
+            g.go_timeout("key", timeout, || Ok(RES)).unwrap();
+            g.go_deadline("key", Instant::now() + timeout, || Ok(RES))
+                .unwrap();
+            g.go_timeout_with_context("key", timeout, |_| Ok(RES))
+                .unwrap();
+        });
+        let spans = recorder.spans.lock();
+        let op = |id: u64| spans[&id]["op"].as_str();
+        assert_eq!((op(1), op(2)), ("go", "go"));
+        assert_eq!(
+            (op(3), op(4), op(5)),
+            ("go_timeout", "go_deadline", "go_timeout_with_context")
+        );
+    }
 }
@@ -0,0 +1,16 @@
+/// Overflow decides what happens to a caller of a key whose call already
+/// has as many duplicate callers as
+/// [`Group::max_waiters_per_key`](crate::Group::max_waiters_per_key)
+/// allows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail the caller right away with [`Error::TooManyWaiters`].
+    ///
+    /// [`Error::TooManyWaiters`]: crate::Error::TooManyWaiters
+    #[default]
+    Reject,
+
+    /// Let the caller run its own func without joining the call. Its res is
+    /// neither shared nor kept.
+    Run,
+}
@@ -0,0 +1,11 @@
+/// Served is a value handed out by [`Group::go_stale`](crate::Group::go_stale).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Served<T> {
+    pub val: T,
+
+    /// Whether val was given to multiple callers.
+    pub shared: bool,
+
+    /// Whether val had expired and a call refreshing it is in flight.
+    pub stale: bool,
+}
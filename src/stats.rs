@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Stats is a snapshot of the counters of a [`Group`](crate::Group).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Calls made to the group.
+    pub calls: u64,
+
+    /// Calls that executed func.
+    pub executions: u64,
+
+    /// Calls that got their res without executing func, either from a call
+    /// in flight or from a kept value.
+    pub suppressed: u64,
+
+    /// Executions of func currently in flight.
+    pub in_flight: usize,
+
+    /// Duplicate callers currently waiting for a call in flight.
+    pub waiters: usize,
+
+    /// Executions whose func returned an error.
+    pub errors: u64,
+
+    /// Executions whose func panicked.
+    pub panics: u64,
+}
+
+// Counters are updated by a group as calls come and go
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) calls: AtomicU64,
+    pub(crate) executions: AtomicU64,
+    pub(crate) suppressed: AtomicU64,
+    pub(crate) in_flight: AtomicUsize,
+    pub(crate) waiters: AtomicUsize,
+    pub(crate) errors: AtomicU64,
+    pub(crate) panics: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            calls: self.calls.load(Ordering::Relaxed),
+            executions: self.executions.load(Ordering::Relaxed),
+            suppressed: self.suppressed.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            waiters: self.waiters.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            panics: self.panics.load(Ordering::Relaxed),
+        }
+    }
+}
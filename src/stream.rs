@@ -0,0 +1,104 @@
+use std::panic;
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use parking_lot::{Condvar, Mutex};
+
+use crate::Error;
+
+pub(crate) type StreamMap<K, T, E> = Mutex<HashMap<K, Arc<StreamCall<T, E>>>>;
+
+// StreamCall is a streaming call, keeping every item it produced so that
+// callers that join late get the full sequence
+pub(crate) struct StreamCall<T, E> {
+    state: Mutex<StreamState<T, E>>,
+    cond: Condvar,
+}
+
+struct StreamState<T, E> {
+    items: Vec<T>,
+
+    // set once func is done, to Err if it failed
+    end: Option<Result<(), Error<E>>>,
+}
+
+impl<T, E> StreamCall<T, E> {
+    pub(crate) fn new() -> StreamCall<T, E> {
+        StreamCall {
+            state: Mutex::new(StreamState {
+                items: Vec::new(),
+                end: None,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn push(&self, item: T) {
+        self.state.lock().items.push(item);
+        self.cond.notify_all();
+    }
+
+    pub(crate) fn end(&self, end: Result<(), Error<E>>) {
+        self.state.lock().end = Some(end);
+        self.cond.notify_all();
+    }
+}
+
+/// Subscription iterates over the items of a streaming call started with
+/// [`Group::go_stream`](crate::Group::go_stream).
+///
+/// It yields the items produced so far, then blocks for each live one. A
+/// failed call ends with its error. If the call panicked and the group
+/// propagates panics, the panic is re-raised instead.
+pub struct Subscription<T, E> {
+    call: Arc<StreamCall<T, E>>,
+    next: usize,
+    done: bool,
+    propagate_panics: bool,
+}
+
+impl<T, E> Subscription<T, E> {
+    pub(crate) fn new(call: Arc<StreamCall<T, E>>, propagate_panics: bool) -> Subscription<T, E> {
+        Subscription {
+            call,
+            next: 0,
+            done: false,
+            propagate_panics,
+        }
+    }
+}
+
+impl<T, E> Iterator for Subscription<T, E>
+where
+    T: Clone,
+{
+    type Item = Result<T, Error<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut state = self.call.state.lock();
+        while state.items.len() <= self.next && state.end.is_none() {
+            self.call.cond.wait(&mut state);
+        }
+
+        if let Some(item) = state.items.get(self.next) {
+            self.next += 1;
+            return Some(Ok(item.clone()));
+        }
+        self.done = true;
+        let err = match state.end.as_ref() {
+            Some(Err(err)) => err.clone(),
+            _ => return None,
+        };
+        drop(state);
+        match err {
+            Error::Panicked(panicked) if self.propagate_panics => {
+                panic::resume_unwind(Box::new(panicked))
+            }
+            err => Some(Err(err)),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::any::Any;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{Error, Group};
+
+/// SingleflightLayer wraps services in a [`Singleflight`], collapsing
+/// identical requests in flight onto one call of the inner service.
+///
+/// Requests are identical if key_fn maps them to the same key. Each service
+/// the layer wraps gets a group of its own, shared by its clones.
+#[derive(Debug, Clone)]
+pub struct SingleflightLayer<KeyFn> {
+    key_fn: KeyFn,
+}
+
+impl<KeyFn> SingleflightLayer<KeyFn> {
+    pub fn new(key_fn: KeyFn) -> SingleflightLayer<KeyFn> {
+        SingleflightLayer { key_fn }
+    }
+}
+
+impl<S, KeyFn> Layer<S> for SingleflightLayer<KeyFn>
+where
+    KeyFn: Clone,
+{
+    type Service = Singleflight<S, KeyFn>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Singleflight {
+            inner,
+            key_fn: self.key_fn.clone(),
+            group: Arc::default(),
+        }
+    }
+}
+
+/// Singleflight is a service that calls the inner service once for all
+/// identical requests in flight, and hands every caller a clone of its
+/// response. Errors of the inner service are shared as [`Error::Func`].
+#[derive(Clone)]
+pub struct Singleflight<S, KeyFn> {
+    inner: S,
+    key_fn: KeyFn,
+
+    // the group is typed by the requests the service is called with, so it
+    // is created on the first call. Service is implemented for every Req
+    // and the key and response types follow from Req, so they cannot be
+    // parameters of Singleflight without fixing Req when the layer wraps a
+    // service. A service is called with one Req in practice; calling it
+    // with keys or responses of another type panics.
+    group: Arc<OnceLock<Arc<dyn Any + Send + Sync>>>,
+}
+
+impl<S, KeyFn> Singleflight<S, KeyFn> {
+    pub(crate) fn group<K, T, E>(&self) -> Arc<Group<K, T, E>>
+    where
+        K: Hash + Eq + Clone + Send + Sync + 'static,
+        T: Clone + Send + Sync + 'static,
+        E: Send + Sync + 'static,
+    {
+        let group = self
+            .group
+            .get_or_init(|| Arc::new(Group::<K, T, E>::default()));
+        match group.clone().downcast() {
+            Ok(group) => group,
+            Err(_) => panic!("singleflight: service called with keys or responses of another type"),
+        }
+    }
+}
+
+impl<S, KeyFn, Req, K> Service<Req> for Singleflight<S, KeyFn>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Clone + Send + Sync + 'static,
+    S::Error: Send + Sync + 'static,
+    KeyFn: Fn(&Req) -> K,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    Req: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Error<S::Error>;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner
+            .poll_ready(cx)
+            .map_err(|err| Error::Func(Arc::new(err)))
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let key = (self.key_fn)(&req);
+        let group = self.group::<K, S::Response, S::Error>();
+
+        // the inner service was driven to readiness, so it is the one to call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let (res, _) = group.go_async(&key, move || inner.call(req)).await?;
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,185 @@
+// Spans and events of a group's callers, emitted with the tracing feature.
+// Without it, every type here is a no-op the compiler removes.
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use tracing::field::Empty;
+
+    type Render<K> = dyn Fn(&K) -> String + Send + Sync;
+
+    // KeyFmt renders keys for spans. Keys are left out unless the group was
+    // given a way to render them.
+    pub(crate) struct KeyFmt<K>(Option<Arc<Render<K>>>);
+
+    impl<K> Default for KeyFmt<K> {
+        fn default() -> Self {
+            KeyFmt(None)
+        }
+    }
+
+    impl<K> KeyFmt<K> {
+        pub(crate) fn new<F>(f: F) -> KeyFmt<K>
+        where
+            F: Fn(&K) -> String + Send + Sync + 'static,
+        {
+            KeyFmt(Some(Arc::new(f)))
+        }
+
+        pub(crate) fn format<Q>(&self, key: &Q) -> Option<String>
+        where
+            Q: ToOwned<Owned = K> + ?Sized,
+        {
+            self.0.as_ref().map(|f| f(&key.to_owned()))
+        }
+    }
+
+    // Span is the span of one caller of a group
+    #[derive(Clone)]
+    pub(crate) struct Span {
+        span: tracing::Span,
+        start: Instant,
+    }
+
+    // Link is kept with a call so that the spans of its duplicate callers
+    // can follow the span of its execution call
+    #[derive(Default)]
+    pub(crate) struct Link(Option<tracing::Span>);
+
+    impl Span {
+        pub(crate) fn new(op: &'static str, key: impl FnOnce() -> Option<String>) -> Span {
+            let span = tracing::debug_span!(
+                "singleflight",
+                op,
+                key = Empty,
+                role = Empty,
+                dup = Empty,
+                wait_us = Empty,
+            );
+            if !span.is_disabled() {
+                if let Some(key) = key() {
+                    span.record("key", key.as_str());
+                }
+            }
+            Span {
+                span,
+                start: Instant::now(),
+            }
+        }
+
+        // cached records that the caller got a kept res
+        pub(crate) fn cached(&self) {
+            self.span.record("role", "cached");
+            tracing::debug!(parent: &self.span, "served kept res");
+        }
+
+        // stale records that the caller got an expired value
+        pub(crate) fn stale(&self) {
+            self.span.record("role", "stale");
+            tracing::debug!(parent: &self.span, "served stale value");
+        }
+
+        // lead records that the caller executes the call of link
+        pub(crate) fn lead(&self, link: &mut Link) {
+            self.span.record("role", "leader");
+            link.0 = Some(self.span.clone());
+            tracing::debug!(parent: &self.span, "started call");
+        }
+
+        // follow records that the caller joined the call of link as its
+        // dup-th duplicate caller
+        pub(crate) fn follow(&self, link: &Link, dup: usize) {
+            self.span.record("role", "follower");
+            if let Some(leader) = &link.0 {
+                self.span.follows_from(leader);
+            }
+            tracing::debug!(parent: &self.span, dup, "joined call");
+        }
+
+        // received records how long the caller waited for its res
+        pub(crate) fn received(&self) {
+            let wait_us = self.start.elapsed().as_micros() as u64;
+            self.span.record("wait_us", wait_us);
+            tracing::debug!(parent: &self.span, wait_us, "received res");
+        }
+
+        pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+            self.span.in_scope(f)
+        }
+    }
+
+    impl Link {
+        // end records the number of duplicate callers of a call that is over
+        pub(crate) fn end(&self, dup: usize) {
+            if let Some(leader) = &self.0 {
+                leader.record("dup", dup);
+                tracing::debug!(parent: leader, dup, "ended call");
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use std::marker::PhantomData;
+
+    pub(crate) struct KeyFmt<K>(PhantomData<fn(&K)>);
+
+    impl<K> Default for KeyFmt<K> {
+        fn default() -> Self {
+            KeyFmt(PhantomData)
+        }
+    }
+
+    impl<K> KeyFmt<K> {
+        #[inline]
+        pub(crate) fn format<Q>(&self, _key: &Q) -> Option<String>
+        where
+            Q: ToOwned<Owned = K> + ?Sized,
+        {
+            None
+        }
+    }
+
+    #[derive(Clone)]
+    pub(crate) struct Span;
+
+    #[derive(Default)]
+    pub(crate) struct Link(());
+
+    impl Span {
+        #[inline]
+        pub(crate) fn new(_op: &'static str, _key: impl FnOnce() -> Option<String>) -> Span {
+            Span
+        }
+
+        #[inline]
+        pub(crate) fn cached(&self) {}
+
+        #[inline]
+        pub(crate) fn stale(&self) {}
+
+        #[inline]
+        pub(crate) fn lead(&self, _link: &mut Link) {}
+
+        #[inline]
+        pub(crate) fn follow(&self, _link: &Link, _dup: usize) {}
+
+        #[inline]
+        pub(crate) fn received(&self) {}
+
+        #[inline]
+        pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+            f()
+        }
+    }
+
+    impl Link {
+        #[inline]
+        pub(crate) fn end(&self, _dup: usize) {}
+    }
+}
+
+pub(crate) use imp::{KeyFmt, Link, Span};